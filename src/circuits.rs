@@ -0,0 +1,621 @@
+//! Circuit models selectable in the plugin.
+//!
+//! [`Circuits`] holds the parameters and state of every model for a single channel, so that the
+//! plugin only has to forward its parameters and dispatch on the selected [`FilterType`].
+
+use nih_plug::prelude::Enum;
+
+use crate::lpf::{
+    ActiveLpf, ActiveLpfState, ActiveLpfZdfState, Baxandall, BaxandallState, DiodeLadder,
+    DiodeLadderState, LowpassGate, LowpassGateState, MfbBandpass, MfbLowpass, MfbState, MoogLadder,
+    MoogLadderState, Ms20Highpass, Ms20Lowpass, OtaCascade, OtaCascadeState, Phaser, PhaserElement,
+    PhaserLfo, PhaserState, RcLadder, RcLadderState, SallenKey, SallenKeyBandpass,
+    SallenKeyHighpass, SallenKeyState, SteinerParker, Svf, SvfState, ToneStack,
+    ToneStackComponents, ToneStackState, VactrolRc, VactrolRcState, VoxToneStack, Wah, WahState,
+};
+use crate::ode::{DormandPrince, Exponential, Solver, Trapezoidal};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+pub enum FilterType {
+    #[name = "Active lowpass"]
+    ActiveLpf,
+    #[name = "Active lowpass (ZDF)"]
+    ActiveLpfZdf,
+    #[name = "Sallen-Key lowpass"]
+    SallenKeyLowpass,
+    #[name = "Sallen-Key highpass"]
+    SallenKeyHighpass,
+    #[name = "Sallen-Key bandpass"]
+    SallenKeyBandpass,
+    #[name = "Moog ladder"]
+    MoogLadder,
+    #[name = "Diode ladder"]
+    DiodeLadder,
+    #[name = "MS-20 lowpass"]
+    Ms20Lowpass,
+    #[name = "MS-20 highpass"]
+    Ms20Highpass,
+    #[name = "State variable"]
+    Svf,
+    #[name = "OTA cascade"]
+    OtaCascade,
+    #[name = "Steiner-Parker"]
+    SteinerParker,
+    #[name = "MFB lowpass"]
+    MfbLowpass,
+    #[name = "MFB bandpass"]
+    MfbBandpass,
+    #[name = "RC ladder"]
+    RcLadder,
+    #[name = "Fender tone stack"]
+    FenderToneStack,
+    #[name = "Marshall tone stack"]
+    MarshallToneStack,
+    #[name = "Vox tone stack"]
+    VoxToneStack,
+    #[name = "Baxandall"]
+    Baxandall,
+    #[name = "Wah"]
+    Wah,
+    #[name = "JFET phaser"]
+    JfetPhaser,
+    #[name = "OTA phaser"]
+    OtaPhaser,
+    #[name = "Lowpass gate"]
+    LowpassGate,
+    #[name = "Vactrol RC"]
+    VactrolRc,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Circuits {
+    active_lpf: ActiveLpf,
+    active_lpf_state: ActiveLpfState,
+    active_lpf_zdf_state: ActiveLpfZdfState,
+    sk_lowpass: SallenKey,
+    sk_lowpass_state: SallenKeyState<SallenKey>,
+    sk_highpass: SallenKeyHighpass,
+    sk_highpass_state: SallenKeyState<SallenKeyHighpass>,
+    sk_bandpass: SallenKeyBandpass,
+    sk_bandpass_state: SallenKeyState<SallenKeyBandpass>,
+    moog_ladder: MoogLadder,
+    moog_ladder_state: MoogLadderState,
+    diode_ladder: DiodeLadder,
+    diode_ladder_state: DiodeLadderState,
+    ms20_lowpass: Ms20Lowpass,
+    ms20_lowpass_state: SallenKeyState<Ms20Lowpass>,
+    ms20_highpass: Ms20Highpass,
+    ms20_highpass_state: SallenKeyState<Ms20Highpass>,
+    svf: Svf,
+    svf_state: SvfState,
+    ota: OtaCascade,
+    ota_state: OtaCascadeState,
+    steiner_parker: SteinerParker,
+    steiner_parker_state: SallenKeyState<SteinerParker>,
+    mfb_lowpass: MfbLowpass,
+    mfb_lowpass_state: MfbState<MfbLowpass>,
+    mfb_bandpass: MfbBandpass,
+    mfb_bandpass_state: MfbState<MfbBandpass>,
+    rc_ladder: RcLadder,
+    rc_ladder_state: RcLadderState,
+    fender_stack: ToneStack,
+    fender_stack_state: ToneStackState,
+    marshall_stack: ToneStack,
+    marshall_stack_state: ToneStackState,
+    vox_stack: VoxToneStack,
+    vox_stack_state: ToneStackState<VoxToneStack>,
+    baxandall: Baxandall,
+    baxandall_state: BaxandallState,
+    wah: Wah,
+    wah_state: WahState,
+    jfet_phaser: Phaser,
+    jfet_phaser_state: PhaserState,
+    ota_phaser: Phaser,
+    ota_phaser_state: PhaserState,
+    /// LFO shared by the phasers, so that switching between them keeps the sweep going.
+    phaser_lfo: PhaserLfo,
+    lowpass_gate: LowpassGate,
+    lowpass_gate_state: LowpassGateState,
+    vactrol_rc: VactrolRc,
+    vactrol_rc_state: VactrolRcState,
+    /// Normalized resonance, kept to rescale the OTA feedback when its number of poles changes.
+    resonance: f64,
+}
+
+impl Circuits {
+    /// Saturation current of the wah inductor, low enough for line-level signals to reach it.
+    const WAH_SATURATION_CURRENT: f64 = 0.3e-3;
+
+    pub fn new(fc: f64, q: f64) -> Self {
+        // The multiple-feedback filters keep the Q and gain of their components, which are a
+        // Butterworth lowpass and a bandpass with a Q of about 6.8, both with unity gain
+        let mut mfb_lowpass = MfbLowpass::new(10e3, 10e3, 10e3, 45e-9, 10e-9);
+        mfb_lowpass.set_fc(fc);
+        let mut mfb_bandpass = MfbBandpass::new(100e3, 1.1e3, 200e3, 10e-9, 10e-9);
+        mfb_bandpass.set_fc(fc);
+        let mut jfet_phaser = Phaser::default();
+        jfet_phaser.set_fc(fc);
+        let mut ota_phaser = Phaser::new(PhaserElement::Ota);
+        ota_phaser.set_fc(fc);
+        Self {
+            active_lpf: ActiveLpf::new(fc),
+            active_lpf_state: ActiveLpfState::default(),
+            active_lpf_zdf_state: ActiveLpfZdfState::default(),
+            sk_lowpass: SallenKey::new(fc, q),
+            sk_lowpass_state: SallenKeyState::default(),
+            sk_highpass: SallenKeyHighpass::new(fc, q),
+            sk_highpass_state: SallenKeyState::default(),
+            sk_bandpass: SallenKeyBandpass::new(fc, q),
+            sk_bandpass_state: SallenKeyState::default(),
+            moog_ladder: MoogLadder::new(fc, 0.),
+            moog_ladder_state: MoogLadderState::default(),
+            diode_ladder: DiodeLadder::new(fc, 0.),
+            diode_ladder_state: DiodeLadderState::default(),
+            ms20_lowpass: Ms20Lowpass::new(fc, q),
+            ms20_lowpass_state: SallenKeyState::default(),
+            ms20_highpass: Ms20Highpass::new(fc, q),
+            ms20_highpass_state: SallenKeyState::default(),
+            svf: Svf::new(fc, q),
+            svf_state: SvfState::default(),
+            ota: OtaCascade::new(0., 4),
+            ota_state: OtaCascadeState::default(),
+            steiner_parker: SteinerParker::new(fc, q),
+            steiner_parker_state: SallenKeyState::default(),
+            mfb_lowpass,
+            mfb_lowpass_state: MfbState::default(),
+            mfb_bandpass,
+            mfb_bandpass_state: MfbState::default(),
+            rc_ladder: RcLadder::new(fc, 4),
+            rc_ladder_state: RcLadderState::default(),
+            fender_stack: ToneStack::new(ToneStackComponents::FENDER_BASSMAN),
+            fender_stack_state: ToneStackState::default(),
+            marshall_stack: ToneStack::new(ToneStackComponents::MARSHALL_JCM800),
+            marshall_stack_state: ToneStackState::default(),
+            vox_stack: VoxToneStack::default(),
+            vox_stack_state: ToneStackState::default(),
+            baxandall: Baxandall::default(),
+            baxandall_state: BaxandallState::default(),
+            wah: Wah::default(),
+            wah_state: WahState::default(),
+            jfet_phaser,
+            jfet_phaser_state: PhaserState::default(),
+            ota_phaser,
+            ota_phaser_state: PhaserState::default(),
+            phaser_lfo: PhaserLfo::default(),
+            lowpass_gate: LowpassGate::default(),
+            lowpass_gate_state: LowpassGateState::default(),
+            vactrol_rc: VactrolRc::default(),
+            vactrol_rc_state: VactrolRcState::default(),
+            resonance: 0.,
+        }
+    }
+
+    pub fn set_fc(&mut self, fc: f64) {
+        self.active_lpf.set_fc(fc);
+        self.sk_lowpass.set_fc(fc);
+        self.sk_highpass.set_fc(fc);
+        self.sk_bandpass.set_fc(fc);
+        self.moog_ladder.set_fc(fc);
+        self.diode_ladder.set_fc(fc);
+        self.ms20_lowpass.set_fc(fc);
+        self.ms20_highpass.set_fc(fc);
+        self.svf.set_fc(fc);
+        self.ota.set_cv(self.ota.cv_for(fc));
+        self.steiner_parker.set_fc(fc);
+        self.mfb_lowpass.set_fc(fc);
+        self.mfb_bandpass.set_fc(fc);
+        self.rc_ladder.set_fc(fc);
+        self.jfet_phaser.set_fc(fc);
+        self.ota_phaser.set_fc(fc);
+    }
+
+    pub fn set_q(&mut self, q: f64) {
+        self.sk_lowpass.set_q(q);
+        self.sk_highpass.set_q(q);
+        self.sk_bandpass.set_q(q);
+        self.ms20_lowpass.set_q(q);
+        self.ms20_highpass.set_q(q);
+        self.steiner_parker.set_q(q);
+    }
+
+    pub fn set_drive(&mut self, drive: f64) {
+        self.ms20_lowpass.set_drive(drive);
+        self.ms20_highpass.set_drive(drive);
+    }
+
+    /// Set the resonance of the ladder filters, normalized so that they self-oscillate at 1.
+    pub fn set_resonance(&mut self, resonance: f64) {
+        self.moog_ladder
+            .set_resonance(resonance * MoogLadder::SELF_OSCILLATION);
+        self.diode_ladder
+            .set_resonance(resonance * DiodeLadder::SELF_OSCILLATION);
+        self.svf.set_resonance(resonance * Svf::SELF_OSCILLATION);
+        // The OTA cascade doesn't self-oscillate with fewer than 3 poles, in which case the
+        // feedback spans the same range as with 4
+        self.ota
+            .set_resonance(resonance * self.ota.self_oscillation().unwrap_or(4.));
+        self.resonance = resonance;
+    }
+
+    /// Set the number of poles of the OTA cascade, between 1 and 4.
+    pub fn set_poles(&mut self, poles: usize) {
+        self.ota.set_poles(poles);
+        self.set_resonance(self.resonance);
+    }
+
+    /// Set the number of stages of the RC ladder. As it is fixed at construction, this rebuilds
+    /// the ladder and clears its state when the order changes.
+    pub fn set_order(&mut self, order: usize) {
+        if order != self.rc_ladder.order() {
+            self.rc_ladder = RcLadder::new(self.rc_ladder.fc(), order);
+            self.rc_ladder_state.reset();
+        }
+    }
+
+    /// Set the bass, mid and treble pot positions of the tone stacks, leaving the mid out for the
+    /// Vox stack and the Baxandall which have no such control.
+    pub fn set_tone_stack(&mut self, bass: f64, mid: f64, treble: f64) {
+        for stack in [&mut self.fender_stack, &mut self.marshall_stack] {
+            stack.set_bass(bass);
+            stack.set_mid(mid);
+            stack.set_treble(treble);
+        }
+        self.vox_stack.set_bass(bass);
+        self.vox_stack.set_treble(treble);
+        self.baxandall.set_bass(bass);
+        self.baxandall.set_treble(treble);
+    }
+
+    /// Set the position of the wah pedal, from heel at 0 to toe at 1.
+    pub fn set_pedal(&mut self, pedal: f64) {
+        self.wah.set_pedal(pedal);
+    }
+
+    pub fn set_wah_saturation(&mut self, enabled: bool) {
+        self.wah
+            .set_saturation(enabled.then_some(Self::WAH_SATURATION_CURRENT));
+    }
+
+    /// Set the control signal of the vactrols of the lowpass gate and the vactrol RC stage, between
+    /// 0 and 1.
+    pub fn set_gate(&mut self, gate: f64) {
+        self.lowpass_gate.set_cv(gate);
+        self.vactrol_rc.set_cv(gate);
+    }
+
+    /// Set the LFO rate in Hz and sweep depth of the phasers, along with their feedback and
+    /// number of stages.
+    pub fn set_phaser(&mut self, rate: f64, depth: f64, feedback: f64, stages: usize) {
+        for phaser in [&mut self.jfet_phaser, &mut self.ota_phaser] {
+            phaser.set_rate(rate);
+            phaser.set_depth(depth);
+            phaser.set_feedback(feedback);
+            phaser.set_stages(stages);
+        }
+    }
+
+    pub fn set_bass_compensation(&mut self, enabled: bool) {
+        self.moog_ladder
+            .set_bass_compensation(if enabled { 1. } else { 0. });
+    }
+
+    /// Set the output of the state variable filter, from lowpass at 0 to highpass at 1, and
+    /// crossfade the Steiner-Parker inputs from lowpass to bandpass to highpass.
+    pub fn set_morph(&mut self, morph: f64) {
+        self.svf.set_morph(morph);
+        let morph = 2. * morph.clamp(0., 1.);
+        if morph <= 1. {
+            self.steiner_parker.set_input_weights(1. - morph, morph, 0.);
+        } else {
+            self.steiner_parker
+                .set_input_weights(0., 2. - morph, morph - 1.);
+        }
+    }
+
+    pub fn set_amp(&mut self, amp: f64) {
+        self.active_lpf.set_amp(amp);
+    }
+
+    /// Pick the solvers: the adaptive one when rendering offline, where accuracy matters more than
+    /// CPU time. Otherwise, the RC stages, the RC ladder, the tone stacks, the Baxandall and the
+    /// lowpass gate are linear so their exact discretization keeps the cutoff unwarped up to
+    /// Nyquist, and the nonlinear circuits use the implicit trapezoidal rule which stays stable at
+    /// high cutoffs. The zero-delay feedback lowpass keeps its own integrator either way.
+    pub fn set_offline(&mut self, offline: bool) {
+        if offline {
+            self.active_lpf_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.set_sallen_key_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.moog_ladder_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.diode_ladder_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.svf_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.ota_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.rc_ladder_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.fender_stack_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.marshall_stack_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.vox_stack_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.baxandall_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.jfet_phaser_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.ota_phaser_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.lowpass_gate_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+            self.vactrol_rc_state
+                .set_solver(Solver::DormandPrince(DormandPrince::default()));
+        } else {
+            self.active_lpf_state
+                .set_solver(Solver::Exponential(Exponential::default()));
+            self.set_sallen_key_solver(Solver::Trapezoidal(Trapezoidal::default()));
+            self.moog_ladder_state
+                .set_solver(Solver::Trapezoidal(Trapezoidal::default()));
+            self.diode_ladder_state
+                .set_solver(Solver::Trapezoidal(Trapezoidal::default()));
+            self.svf_state
+                .set_solver(Solver::Trapezoidal(Trapezoidal::default()));
+            self.ota_state
+                .set_solver(Solver::Trapezoidal(Trapezoidal::default()));
+            self.rc_ladder_state
+                .set_solver(Solver::Exponential(Exponential::default()));
+            self.fender_stack_state
+                .set_solver(Solver::Exponential(Exponential::default()));
+            self.marshall_stack_state
+                .set_solver(Solver::Exponential(Exponential::default()));
+            self.vox_stack_state
+                .set_solver(Solver::Exponential(Exponential::default()));
+            self.baxandall_state
+                .set_solver(Solver::Exponential(Exponential::default()));
+            self.jfet_phaser_state
+                .set_solver(Solver::Trapezoidal(Trapezoidal::default()));
+            self.ota_phaser_state
+                .set_solver(Solver::Trapezoidal(Trapezoidal::default()));
+            self.lowpass_gate_state
+                .set_solver(Solver::Exponential(Exponential::default()));
+            self.vactrol_rc_state
+                .set_solver(Solver::Exponential(Exponential::default()));
+        }
+    }
+
+    fn set_sallen_key_solver(&mut self, solver: Solver<2>) {
+        self.sk_lowpass_state.set_solver(solver);
+        self.sk_highpass_state.set_solver(solver);
+        self.sk_bandpass_state.set_solver(solver);
+        self.ms20_lowpass_state.set_solver(solver);
+        self.ms20_highpass_state.set_solver(solver);
+        self.steiner_parker_state.set_solver(solver);
+        self.mfb_lowpass_state.set_solver(solver);
+        self.mfb_bandpass_state.set_solver(solver);
+        self.wah_state.set_solver(solver);
+    }
+
+    pub fn set_substeps(&mut self, substeps: usize) {
+        self.active_lpf_state.set_substeps(substeps);
+        self.active_lpf_zdf_state.set_substeps(substeps);
+        self.sk_lowpass_state.set_substeps(substeps);
+        self.sk_highpass_state.set_substeps(substeps);
+        self.sk_bandpass_state.set_substeps(substeps);
+        self.moog_ladder_state.set_substeps(substeps);
+        self.diode_ladder_state.set_substeps(substeps);
+        self.ms20_lowpass_state.set_substeps(substeps);
+        self.ms20_highpass_state.set_substeps(substeps);
+        self.svf_state.set_substeps(substeps);
+        self.ota_state.set_substeps(substeps);
+        self.steiner_parker_state.set_substeps(substeps);
+        self.mfb_lowpass_state.set_substeps(substeps);
+        self.mfb_bandpass_state.set_substeps(substeps);
+        self.rc_ladder_state.set_substeps(substeps);
+        self.fender_stack_state.set_substeps(substeps);
+        self.marshall_stack_state.set_substeps(substeps);
+        self.vox_stack_state.set_substeps(substeps);
+        self.baxandall_state.set_substeps(substeps);
+        self.wah_state.set_substeps(substeps);
+        self.jfet_phaser_state.set_substeps(substeps);
+        self.ota_phaser_state.set_substeps(substeps);
+        self.lowpass_gate_state.set_substeps(substeps);
+        self.vactrol_rc_state.set_substeps(substeps);
+    }
+
+    pub fn reset(&mut self) {
+        self.active_lpf_state.reset();
+        self.active_lpf_zdf_state.reset();
+        self.sk_lowpass_state.reset();
+        self.sk_highpass_state.reset();
+        self.sk_bandpass_state.reset();
+        self.moog_ladder_state.reset();
+        self.diode_ladder_state.reset();
+        self.ms20_lowpass_state.reset();
+        self.ms20_highpass_state.reset();
+        self.svf_state.reset();
+        self.ota_state.reset();
+        self.steiner_parker_state.reset();
+        self.mfb_lowpass_state.reset();
+        self.mfb_bandpass_state.reset();
+        self.rc_ladder_state.reset();
+        self.fender_stack_state.reset();
+        self.marshall_stack_state.reset();
+        self.vox_stack_state.reset();
+        self.baxandall_state.reset();
+        self.wah_state.reset();
+        self.jfet_phaser_state.reset();
+        self.ota_phaser_state.reset();
+        self.phaser_lfo.reset();
+        self.lowpass_gate_state.reset();
+        self.vactrol_rc_state.reset();
+    }
+
+    /// Process one sample through the circuit of type `ty`.
+    pub fn process(&mut self, ty: FilterType, v_in: f64, step: f64) -> f64 {
+        match ty {
+            FilterType::ActiveLpf => {
+                self.active_lpf_state.set_v_in(v_in);
+                self.active_lpf_state.process(&self.active_lpf, step)
+            }
+            FilterType::ActiveLpfZdf => {
+                self.active_lpf_zdf_state.set_v_in(v_in);
+                self.active_lpf_zdf_state.process(&self.active_lpf, step)
+            }
+            FilterType::SallenKeyLowpass => {
+                self.sk_lowpass_state.set_v_in(v_in);
+                self.sk_lowpass_state.process(&self.sk_lowpass, step)
+            }
+            FilterType::SallenKeyHighpass => {
+                self.sk_highpass_state.set_v_in(v_in);
+                self.sk_highpass_state.process(&self.sk_highpass, step)
+            }
+            FilterType::SallenKeyBandpass => {
+                self.sk_bandpass_state.set_v_in(v_in);
+                self.sk_bandpass_state.process(&self.sk_bandpass, step)
+            }
+            FilterType::MoogLadder => {
+                self.moog_ladder_state.set_v_in(v_in);
+                self.moog_ladder_state.process(&self.moog_ladder, step)
+            }
+            FilterType::DiodeLadder => {
+                self.diode_ladder_state.set_v_in(v_in);
+                self.diode_ladder_state.process(&self.diode_ladder, step)
+            }
+            FilterType::Ms20Lowpass => {
+                self.ms20_lowpass_state.set_v_in(v_in);
+                self.ms20_lowpass_state.process(&self.ms20_lowpass, step)
+            }
+            FilterType::Ms20Highpass => {
+                self.ms20_highpass_state.set_v_in(v_in);
+                self.ms20_highpass_state.process(&self.ms20_highpass, step)
+            }
+            FilterType::Svf => {
+                self.svf_state.set_v_in(v_in);
+                self.svf_state.process(&self.svf, step)
+            }
+            FilterType::OtaCascade => {
+                self.ota_state.set_v_in(v_in);
+                self.ota_state.process(&self.ota, step)
+            }
+            FilterType::SteinerParker => {
+                self.steiner_parker_state.set_v_in(v_in);
+                self.steiner_parker_state
+                    .process(&self.steiner_parker, step)
+            }
+            FilterType::MfbLowpass => {
+                self.mfb_lowpass_state.set_v_in(v_in);
+                self.mfb_lowpass_state.process(&self.mfb_lowpass, step)
+            }
+            FilterType::MfbBandpass => {
+                self.mfb_bandpass_state.set_v_in(v_in);
+                self.mfb_bandpass_state.process(&self.mfb_bandpass, step)
+            }
+            FilterType::RcLadder => {
+                self.rc_ladder_state.set_v_in(v_in);
+                self.rc_ladder_state.process(&self.rc_ladder, step)
+            }
+            FilterType::FenderToneStack => {
+                self.fender_stack_state.set_v_in(v_in);
+                self.fender_stack_state.process(&self.fender_stack, step)
+            }
+            FilterType::MarshallToneStack => {
+                self.marshall_stack_state.set_v_in(v_in);
+                self.marshall_stack_state
+                    .process(&self.marshall_stack, step)
+            }
+            FilterType::VoxToneStack => {
+                self.vox_stack_state.set_v_in(v_in);
+                self.vox_stack_state.process(&self.vox_stack, step)
+            }
+            FilterType::Baxandall => {
+                self.baxandall_state.set_v_in(v_in);
+                self.baxandall_state.process(&self.baxandall, step)
+            }
+            FilterType::Wah => {
+                self.wah_state.set_v_in(v_in);
+                self.wah_state.process(&self.wah, step)
+            }
+            FilterType::JfetPhaser => {
+                let lfo = self.phaser_lfo.process(self.jfet_phaser.rate(), step);
+                self.jfet_phaser.set_lfo(lfo);
+                self.jfet_phaser_state.set_v_in(v_in);
+                self.jfet_phaser_state.process(&self.jfet_phaser, step)
+            }
+            FilterType::OtaPhaser => {
+                let lfo = self.phaser_lfo.process(self.ota_phaser.rate(), step);
+                self.ota_phaser.set_lfo(lfo);
+                self.ota_phaser_state.set_v_in(v_in);
+                self.ota_phaser_state.process(&self.ota_phaser, step)
+            }
+            FilterType::LowpassGate => {
+                self.lowpass_gate_state.set_v_in(v_in);
+                self.lowpass_gate_state.process(&self.lowpass_gate, step)
+            }
+            FilterType::VactrolRc => {
+                self.vactrol_rc_state.set_v_in(v_in);
+                self.vactrol_rc_state.process(&self.vactrol_rc, step)
+            }
+        }
+    }
+
+    /// Solver steps taken for the last sample processed by the circuit of type `ty`.
+    pub fn substeps(&self, ty: FilterType) -> usize {
+        match ty {
+            FilterType::ActiveLpf => self.active_lpf_state.substeps(),
+            FilterType::ActiveLpfZdf => self.active_lpf_zdf_state.substeps(),
+            FilterType::SallenKeyLowpass => self.sk_lowpass_state.substeps(),
+            FilterType::SallenKeyHighpass => self.sk_highpass_state.substeps(),
+            FilterType::SallenKeyBandpass => self.sk_bandpass_state.substeps(),
+            FilterType::MoogLadder => self.moog_ladder_state.substeps(),
+            FilterType::DiodeLadder => self.diode_ladder_state.substeps(),
+            FilterType::Ms20Lowpass => self.ms20_lowpass_state.substeps(),
+            FilterType::Ms20Highpass => self.ms20_highpass_state.substeps(),
+            FilterType::Svf => self.svf_state.substeps(),
+            FilterType::OtaCascade => self.ota_state.substeps(),
+            FilterType::SteinerParker => self.steiner_parker_state.substeps(),
+            FilterType::MfbLowpass => self.mfb_lowpass_state.substeps(),
+            FilterType::MfbBandpass => self.mfb_bandpass_state.substeps(),
+            FilterType::RcLadder => self.rc_ladder_state.substeps(),
+            FilterType::FenderToneStack => self.fender_stack_state.substeps(),
+            FilterType::MarshallToneStack => self.marshall_stack_state.substeps(),
+            FilterType::VoxToneStack => self.vox_stack_state.substeps(),
+            FilterType::Baxandall => self.baxandall_state.substeps(),
+            FilterType::Wah => self.wah_state.substeps(),
+            FilterType::JfetPhaser => self.jfet_phaser_state.substeps(),
+            FilterType::OtaPhaser => self.ota_phaser_state.substeps(),
+            FilterType::LowpassGate => self.lowpass_gate_state.substeps(),
+            FilterType::VactrolRc => self.vactrol_rc_state.substeps(),
+        }
+    }
+
+    /// Whether any of the simulations diverged and was reset since the last call to this method.
+    pub fn take_diverged(&mut self) -> bool {
+        // Not short-circuiting, so that every flag gets cleared
+        self.active_lpf_state.take_diverged()
+            | self.active_lpf_zdf_state.take_diverged()
+            | self.sk_lowpass_state.take_diverged()
+            | self.sk_highpass_state.take_diverged()
+            | self.sk_bandpass_state.take_diverged()
+            | self.moog_ladder_state.take_diverged()
+            | self.diode_ladder_state.take_diverged()
+            | self.ms20_lowpass_state.take_diverged()
+            | self.ms20_highpass_state.take_diverged()
+            | self.svf_state.take_diverged()
+            | self.ota_state.take_diverged()
+            | self.steiner_parker_state.take_diverged()
+            | self.mfb_lowpass_state.take_diverged()
+            | self.mfb_bandpass_state.take_diverged()
+            | self.rc_ladder_state.take_diverged()
+            | self.fender_stack_state.take_diverged()
+            | self.marshall_stack_state.take_diverged()
+            | self.vox_stack_state.take_diverged()
+            | self.baxandall_state.take_diverged()
+            | self.wah_state.take_diverged()
+            | self.jfet_phaser_state.take_diverged()
+            | self.ota_phaser_state.take_diverged()
+            | self.lowpass_gate_state.take_diverged()
+            | self.vactrol_rc_state.take_diverged()
+    }
+}
@@ -0,0 +1,436 @@
+#![allow(dead_code, clippy::suspicious_arithmetic_impl)]
+//! Forward-mode automatic differentiation.
+//!
+//! [`Dual`] carries a value along with its partial derivatives with respect to `N` independent
+//! variables, and implements [`Float`] so that any function written generically over `T: Float`
+//! can be differentiated by evaluating it once on dual numbers.
+
+use std::num::FpCategory;
+use std::ops;
+
+use num_traits::{Float, Num, NumCast, One, ToPrimitive, Zero};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Dual<const N: usize> {
+    /// Value of the number.
+    pub re: f64,
+    /// Partial derivatives with respect to each of the `N` variables.
+    pub eps: [f64; N],
+}
+
+impl<const N: usize> Dual<N> {
+    /// A value which does not depend on any variable.
+    pub fn constant(re: f64) -> Self {
+        Self { re, eps: [0.; N] }
+    }
+
+    /// The `i`-th independent variable, evaluated at `re`.
+    pub fn variable(re: f64, i: usize) -> Self {
+        let mut eps = [0.; N];
+        eps[i] = 1.;
+        Self { re, eps }
+    }
+
+    /// Apply a scalar function with value `f` and derivative `df` at `self.re`.
+    fn chain(self, f: f64, df: f64) -> Self {
+        Self {
+            re: f,
+            eps: self.eps.map(|e| e * df),
+        }
+    }
+}
+
+impl<const N: usize> PartialEq for Dual<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.re == other.re
+    }
+}
+
+impl<const N: usize> PartialOrd for Dual<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.re.partial_cmp(&other.re)
+    }
+}
+
+impl<const N: usize> ops::Neg for Dual<N> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        self.chain(-self.re, -1.)
+    }
+}
+
+impl<const N: usize> ops::Add for Dual<N> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            re: self.re + rhs.re,
+            eps: std::array::from_fn(|i| self.eps[i] + rhs.eps[i]),
+        }
+    }
+}
+
+impl<const N: usize> ops::Sub for Dual<N> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            re: self.re - rhs.re,
+            eps: std::array::from_fn(|i| self.eps[i] - rhs.eps[i]),
+        }
+    }
+}
+
+impl<const N: usize> ops::Mul for Dual<N> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self {
+            re: self.re * rhs.re,
+            eps: std::array::from_fn(|i| self.eps[i] * rhs.re + self.re * rhs.eps[i]),
+        }
+    }
+}
+
+impl<const N: usize> ops::Div for Dual<N> {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let re = self.re / rhs.re;
+        Self {
+            re,
+            eps: std::array::from_fn(|i| (self.eps[i] - re * rhs.eps[i]) / rhs.re),
+        }
+    }
+}
+
+impl<const N: usize> ops::Rem for Dual<N> {
+    type Output = Self;
+
+    fn rem(self, rhs: Self) -> Self {
+        let n = (self.re / rhs.re).trunc();
+        Self {
+            re: self.re % rhs.re,
+            eps: std::array::from_fn(|i| self.eps[i] - n * rhs.eps[i]),
+        }
+    }
+}
+
+impl<const N: usize> Zero for Dual<N> {
+    fn zero() -> Self {
+        Self::constant(0.)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.re.is_zero()
+    }
+}
+
+impl<const N: usize> One for Dual<N> {
+    fn one() -> Self {
+        Self::constant(1.)
+    }
+}
+
+impl<const N: usize> Num for Dual<N> {
+    type FromStrRadixErr = <f64 as Num>::FromStrRadixErr;
+
+    fn from_str_radix(str: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        f64::from_str_radix(str, radix).map(Self::constant)
+    }
+}
+
+impl<const N: usize> ToPrimitive for Dual<N> {
+    fn to_i64(&self) -> Option<i64> {
+        self.re.to_i64()
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        self.re.to_u64()
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        Some(self.re)
+    }
+}
+
+impl<const N: usize> NumCast for Dual<N> {
+    fn from<T: ToPrimitive>(n: T) -> Option<Self> {
+        n.to_f64().map(Self::constant)
+    }
+}
+
+impl<const N: usize> Float for Dual<N> {
+    fn nan() -> Self {
+        Self::constant(f64::NAN)
+    }
+
+    fn infinity() -> Self {
+        Self::constant(f64::INFINITY)
+    }
+
+    fn neg_infinity() -> Self {
+        Self::constant(f64::NEG_INFINITY)
+    }
+
+    fn neg_zero() -> Self {
+        Self::constant(-0.)
+    }
+
+    fn min_value() -> Self {
+        Self::constant(f64::MIN)
+    }
+
+    fn min_positive_value() -> Self {
+        Self::constant(f64::MIN_POSITIVE)
+    }
+
+    fn max_value() -> Self {
+        Self::constant(f64::MAX)
+    }
+
+    fn is_nan(self) -> bool {
+        self.re.is_nan()
+    }
+
+    fn is_infinite(self) -> bool {
+        self.re.is_infinite()
+    }
+
+    fn is_finite(self) -> bool {
+        self.re.is_finite()
+    }
+
+    fn is_normal(self) -> bool {
+        self.re.is_normal()
+    }
+
+    fn classify(self) -> FpCategory {
+        self.re.classify()
+    }
+
+    fn floor(self) -> Self {
+        self.chain(self.re.floor(), 0.)
+    }
+
+    fn ceil(self) -> Self {
+        self.chain(self.re.ceil(), 0.)
+    }
+
+    fn round(self) -> Self {
+        self.chain(self.re.round(), 0.)
+    }
+
+    fn trunc(self) -> Self {
+        self.chain(self.re.trunc(), 0.)
+    }
+
+    fn fract(self) -> Self {
+        self.chain(self.re.fract(), 1.)
+    }
+
+    fn abs(self) -> Self {
+        self.chain(self.re.abs(), self.re.signum())
+    }
+
+    fn signum(self) -> Self {
+        self.chain(self.re.signum(), 0.)
+    }
+
+    fn is_sign_positive(self) -> bool {
+        self.re.is_sign_positive()
+    }
+
+    fn is_sign_negative(self) -> bool {
+        self.re.is_sign_negative()
+    }
+
+    fn mul_add(self, a: Self, b: Self) -> Self {
+        self * a + b
+    }
+
+    fn recip(self) -> Self {
+        let r = self.re.recip();
+        self.chain(r, -r * r)
+    }
+
+    fn powi(self, n: i32) -> Self {
+        self.chain(self.re.powi(n), n as f64 * self.re.powi(n - 1))
+    }
+
+    fn powf(self, n: Self) -> Self {
+        // d(a^b) = a^b (b' ln a + b a' / a)
+        let re = self.re.powf(n.re);
+        let ln = self.re.ln();
+        Self {
+            re,
+            eps: std::array::from_fn(|i| {
+                let dn = if n.eps[i] == 0. { 0. } else { n.eps[i] * ln };
+                let dx = if self.eps[i] == 0. {
+                    0.
+                } else {
+                    n.re * self.eps[i] / self.re
+                };
+                re * (dn + dx)
+            }),
+        }
+    }
+
+    fn sqrt(self) -> Self {
+        let s = self.re.sqrt();
+        self.chain(s, 0.5 / s)
+    }
+
+    fn exp(self) -> Self {
+        let e = self.re.exp();
+        self.chain(e, e)
+    }
+
+    fn exp2(self) -> Self {
+        let e = self.re.exp2();
+        self.chain(e, e * std::f64::consts::LN_2)
+    }
+
+    fn ln(self) -> Self {
+        self.chain(self.re.ln(), self.re.recip())
+    }
+
+    fn log(self, base: Self) -> Self {
+        self.ln() / base.ln()
+    }
+
+    fn log2(self) -> Self {
+        self.chain(self.re.log2(), (self.re * std::f64::consts::LN_2).recip())
+    }
+
+    fn log10(self) -> Self {
+        self.chain(self.re.log10(), (self.re * std::f64::consts::LN_10).recip())
+    }
+
+    fn max(self, other: Self) -> Self {
+        if self.re >= other.re || other.re.is_nan() {
+            self
+        } else {
+            other
+        }
+    }
+
+    fn min(self, other: Self) -> Self {
+        if self.re <= other.re || other.re.is_nan() {
+            self
+        } else {
+            other
+        }
+    }
+
+    fn abs_sub(self, other: Self) -> Self {
+        (self - other).max(Self::zero())
+    }
+
+    fn cbrt(self) -> Self {
+        let c = self.re.cbrt();
+        self.chain(c, (3. * c * c).recip())
+    }
+
+    fn hypot(self, other: Self) -> Self {
+        (self * self + other * other).sqrt()
+    }
+
+    fn sin(self) -> Self {
+        self.chain(self.re.sin(), self.re.cos())
+    }
+
+    fn cos(self) -> Self {
+        self.chain(self.re.cos(), -self.re.sin())
+    }
+
+    fn tan(self) -> Self {
+        let t = self.re.tan();
+        self.chain(t, 1. + t * t)
+    }
+
+    fn asin(self) -> Self {
+        self.chain(self.re.asin(), (1. - self.re * self.re).sqrt().recip())
+    }
+
+    fn acos(self) -> Self {
+        self.chain(self.re.acos(), -(1. - self.re * self.re).sqrt().recip())
+    }
+
+    fn atan(self) -> Self {
+        self.chain(self.re.atan(), (1. + self.re * self.re).recip())
+    }
+
+    fn atan2(self, other: Self) -> Self {
+        let den = self.re * self.re + other.re * other.re;
+        Self {
+            re: self.re.atan2(other.re),
+            eps: std::array::from_fn(|i| (other.re * self.eps[i] - self.re * other.eps[i]) / den),
+        }
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        (self.sin(), self.cos())
+    }
+
+    fn exp_m1(self) -> Self {
+        self.chain(self.re.exp_m1(), self.re.exp())
+    }
+
+    fn ln_1p(self) -> Self {
+        self.chain(self.re.ln_1p(), (1. + self.re).recip())
+    }
+
+    fn sinh(self) -> Self {
+        self.chain(self.re.sinh(), self.re.cosh())
+    }
+
+    fn cosh(self) -> Self {
+        self.chain(self.re.cosh(), self.re.sinh())
+    }
+
+    fn tanh(self) -> Self {
+        let t = self.re.tanh();
+        self.chain(t, 1. - t * t)
+    }
+
+    fn asinh(self) -> Self {
+        self.chain(self.re.asinh(), (self.re * self.re + 1.).sqrt().recip())
+    }
+
+    fn acosh(self) -> Self {
+        self.chain(self.re.acosh(), (self.re * self.re - 1.).sqrt().recip())
+    }
+
+    fn atanh(self) -> Self {
+        self.chain(self.re.atanh(), (1. - self.re * self.re).recip())
+    }
+
+    fn integer_decode(self) -> (u64, i16, i8) {
+        self.re.integer_decode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use num_traits::Float;
+
+    use super::Dual;
+
+    fn f<T: Float>(x: T, y: T) -> T {
+        (x * y).sin() + x.tanh() / y + x.powf(y)
+    }
+
+    #[test]
+    fn test_partial_derivatives() {
+        let (x, y) = (0.7, 1.3);
+        let res = f(Dual::<2>::variable(x, 0), Dual::variable(y, 1));
+        let dfdx = y * f64::cos(x * y) + (1. - x.tanh().powi(2)) / y + y * x.powf(y - 1.);
+        let dfdy = x * f64::cos(x * y) - x.tanh() / (y * y) + x.powf(y) * x.ln();
+        assert_abs_diff_eq!(res.re, f(x, y));
+        assert_abs_diff_eq!(res.eps[0], dfdx, epsilon = 1e-12);
+        assert_abs_diff_eq!(res.eps[1], dfdy, epsilon = 1e-12);
+    }
+}
@@ -1,32 +1,84 @@
 #![allow(clippy::needless_range_loop)]
+mod circuits;
+mod dual;
 mod fft_convolve;
 mod lpf;
 mod math;
+mod ode;
 mod overlap_add;
 mod oversampling;
+mod state_space;
 mod utils;
 
 
-use lpf::{ActiveLpf, ActiveLpfState};
+use circuits::{Circuits, FilterType};
+use lpf::{Phaser, RcLadder};
 
 use nih_plug::{prelude::*};
 use oversampling::Oversample;
-use std::{sync::Arc};
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
 
 
 struct Filtersim<const CHANNELS: usize> {
     params: Arc<FiltersimParams>,
-    filter: [ActiveLpf; CHANNELS],
-    state: [ActiveLpfState; CHANNELS],
+    circuits: [Circuits; CHANNELS],
     oversample: [Oversample; CHANNELS],
+    /// Highest number of solver substeps taken for a single oversampled sample in the last
+    /// processed block, for an editor or the host to read so that stiff passages can be found.
+    /// Only goes above the substeps parameter with the adaptive solver used for offline rendering.
+    max_substeps: Arc<AtomicUsize>,
+    /// Raised when the simulation of a channel diverged and had to be reset, for an editor or the
+    /// host to read. Cleared when the plugin is reset.
+    diverged: Arc<AtomicBool>,
 }
 
 #[derive(Params)]
 struct FiltersimParams {
     #[id = "freq"]
     pub freq: FloatParam,
+    #[id = "q"]
+    pub q: FloatParam,
+    #[id = "resonance"]
+    pub resonance: FloatParam,
+    #[id = "bass_comp"]
+    pub bass_compensation: BoolParam,
+    #[id = "drive"]
+    pub drive: FloatParam,
+    #[id = "morph"]
+    pub morph: FloatParam,
+    #[id = "poles"]
+    pub poles: IntParam,
+    #[id = "order"]
+    pub order: IntParam,
+    #[id = "bass"]
+    pub bass: FloatParam,
+    #[id = "mid"]
+    pub mid: FloatParam,
+    #[id = "treble"]
+    pub treble: FloatParam,
+    #[id = "pedal"]
+    pub pedal: FloatParam,
+    #[id = "wah_sat"]
+    pub wah_saturation: BoolParam,
+    #[id = "gate"]
+    pub gate: FloatParam,
+    #[id = "rate"]
+    pub rate: FloatParam,
+    #[id = "depth"]
+    pub depth: FloatParam,
+    #[id = "feedback"]
+    pub feedback: FloatParam,
+    #[id = "stages"]
+    pub stages: IntParam,
     #[id = "amp"]
     pub amp: FloatParam,
+    #[id = "type"]
+    pub filter_type: EnumParam<FilterType>,
+    #[id = "substeps"]
+    pub substeps: IntParam,
 }
 
 const BLOCK_SIZE: usize = 64;
@@ -36,9 +88,10 @@ impl<const C: usize> Default for Filtersim<C> {
     fn default() -> Self {
         Self {
             params: Arc::new(FiltersimParams::default()),
-            filter: [ActiveLpf::new(300.0); C],
-            state: [ActiveLpfState::default(); C],
+            circuits: [Circuits::new(300.0, std::f64::consts::FRAC_1_SQRT_2); C],
             oversample: std::array::from_fn(|_| Oversample::new(OVERSAMPLE, BLOCK_SIZE)),
+            max_substeps: Arc::new(AtomicUsize::new(0)),
+            diverged: Arc::new(AtomicBool::new(false)),
         }
     }
 }
@@ -58,6 +111,152 @@ impl Default for FiltersimParams {
             .with_smoother(SmoothingStyle::Linear(0.01))
             .with_value_to_string(formatters::v2s_f32_hz_then_khz(2))
             .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            q: FloatParam::new(
+                "Q",
+                std::f32::consts::FRAC_1_SQRT_2,
+                FloatRange::Skewed {
+                    min: 0.5,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(0.01))
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            resonance: FloatParam::new(
+                "Resonance",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.2,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(0.01))
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage())
+            .with_unit("%"),
+            bass_compensation: BoolParam::new("Bass compensation", false),
+            drive: FloatParam::new(
+                "Drive",
+                1.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 10.0,
+                    factor: FloatRange::gain_skew_factor(0.0, 20.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(0.01))
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            morph: FloatParam::new(
+                "Morph",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(0.01))
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            poles: IntParam::new("Poles", 4, IntRange::Linear { min: 1, max: 4 }),
+            order: IntParam::new(
+                "Order",
+                4,
+                IntRange::Linear {
+                    min: 1,
+                    max: RcLadder::MAX_ORDER as i32,
+                },
+            ),
+            bass: FloatParam::new(
+                "Bass",
+                0.5,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(0.01))
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            mid: FloatParam::new(
+                "Mid",
+                0.5,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(0.01))
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            treble: FloatParam::new(
+                "Treble",
+                0.5,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(0.01))
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            pedal: FloatParam::new(
+                "Pedal",
+                0.5,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(0.01))
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            wah_saturation: BoolParam::new("Inductor saturation", false),
+            gate: FloatParam::new(
+                "Gate",
+                1.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            rate: FloatParam::new(
+                "Rate",
+                0.5,
+                FloatRange::Skewed {
+                    min: 0.05,
+                    max: 10.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(2))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            depth: FloatParam::new(
+                "Depth",
+                0.5,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(0.01))
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            feedback: FloatParam::new(
+                "Feedback",
+                0.0,
+                FloatRange::Linear {
+                    min: -0.95,
+                    max: 0.95,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(0.01))
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage())
+            .with_unit("%"),
+            stages: IntParam::new(
+                "Stages",
+                4,
+                IntRange::Linear {
+                    min: 1,
+                    max: Phaser::MAX_STAGES as i32,
+                },
+            ),
             amp: FloatParam::new(
                 "Amp",
                 1.0,
@@ -68,6 +267,8 @@ impl Default for FiltersimParams {
             )
             .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            filter_type: EnumParam::new("Type", FilterType::ActiveLpf),
+            substeps: IntParam::new("Substeps", 1, IntRange::Linear { min: 1, max: 16 }),
         }
     }
 }
@@ -103,16 +304,26 @@ impl<const CHANNELS: usize> Plugin for Filtersim<CHANNELS> {
     fn initialize(
         &mut self,
         _bus_config: &BusConfig,
-        _buffer_config: &BufferConfig,
+        buffer_config: &BufferConfig,
         context: &mut impl InitContext,
     ) -> bool {
+        let offline = matches!(buffer_config.process_mode, ProcessMode::Offline);
+        for circuits in self.circuits.iter_mut() {
+            circuits.set_offline(offline);
+        }
         context.set_latency_samples(self.oversample[0].latency_samples());
         true
     }
 
     fn reset(&mut self) {
-        // Reset buffers and envelopes here. This can be called from the audio thread and may not
-        // allocate. You can remove this function if you do not need it.
+        for circuits in self.circuits.iter_mut() {
+            circuits.reset();
+        }
+        for oversample in self.oversample.iter_mut() {
+            oversample.reset();
+        }
+        self.max_substeps.store(0, Ordering::Relaxed);
+        self.diverged.store(false, Ordering::Relaxed);
     }
 
     fn process(
@@ -123,28 +334,79 @@ impl<const CHANNELS: usize> Plugin for Filtersim<CHANNELS> {
     ) -> ProcessStatus {
         let sr = context.transport().sample_rate as f64;
         let os_sr = OVERSAMPLE as f64 * sr;
-        let _os_sr_step = os_sr.recip();
+        let os_sr_step = os_sr.recip();
         // Smoothing is optionally built into the parameters themselves
         let amp = self.params.amp.value();
-        for filter in self.filter.iter_mut() {
-            filter.set_amp(amp as _);
+        let freq = self.params.freq.value();
+        let q = self.params.q.value();
+        let resonance = self.params.resonance.value();
+        let bass_compensation = self.params.bass_compensation.value();
+        let drive = self.params.drive.value();
+        let morph = self.params.morph.value();
+        let poles = self.params.poles.value() as usize;
+        let order = self.params.order.value() as usize;
+        let bass = self.params.bass.value();
+        let mid = self.params.mid.value();
+        let treble = self.params.treble.value();
+        let wah_saturation = self.params.wah_saturation.value();
+        let rate = self.params.rate.value();
+        let depth = self.params.depth.value();
+        let feedback = self.params.feedback.value();
+        let stages = self.params.stages.value() as usize;
+        let filter_type = self.params.filter_type.value();
+        let substeps = self.params.substeps.value() as usize;
+        for circuits in self.circuits.iter_mut() {
+            circuits.set_amp(amp as _);
+            circuits.set_fc(freq as _);
+            circuits.set_q(q as _);
+            circuits.set_resonance(resonance as _);
+            circuits.set_bass_compensation(bass_compensation);
+            circuits.set_drive(drive as _);
+            circuits.set_morph(morph as _);
+            circuits.set_poles(poles);
+            circuits.set_order(order);
+            circuits.set_tone_stack(bass as _, mid as _, treble as _);
+            circuits.set_wah_saturation(wah_saturation);
+            circuits.set_phaser(rate as _, depth as _, feedback as _, stages);
+            circuits.set_substeps(substeps);
         }
 
         let mut f64_block = [0.; BLOCK_SIZE];
+        // The pedal and the gate are followed sample by sample, so that sweeping the pedal doesn't
+        // step the wah resonance at every block, and the vactrol sees the gate edges where they
+        // are. The gate isn't smoothed, as the vactrol is slow enough on its own.
+        let mut pedal_block = [0.; BLOCK_SIZE];
+        let mut gate_block = [0.; BLOCK_SIZE];
         for (_i, block) in buffer.iter_blocks(BLOCK_SIZE) {
+            let len = block.samples();
+            for (pedal, gate) in pedal_block.iter_mut().zip(gate_block.iter_mut()).take(len) {
+                *pedal = self.params.pedal.smoothed.next() as f64;
+                *gate = self.params.gate.smoothed.next() as f64;
+            }
+            pedal_block[len..].fill(pedal_block[len - 1]);
+            gate_block[len..].fill(gate_block[len - 1]);
+            let mut max_substeps = 0;
             for (ch, block) in block.into_iter().enumerate() {
                 for (s64, s) in f64_block.iter_mut().zip(block.iter().copied()) {
                     *s64 = s as _;
                 }
-                self.oversample[ch].with_oversample(&mut f64_block, |_data| {
-                    /*                     for ele in data {
-                        *ele = (*ele).clamp(-0.1, 0.1);
-                    } */
+                let circuits = &mut self.circuits[ch];
+                self.oversample[ch].with_oversample(&mut f64_block, |data| {
+                    for (i, s) in data.iter_mut().enumerate() {
+                        circuits.set_pedal(pedal_block[i / OVERSAMPLE]);
+                        circuits.set_gate(gate_block[i / OVERSAMPLE]);
+                        *s = circuits.process(filter_type, *s, os_sr_step);
+                        max_substeps = max_substeps.max(circuits.substeps(filter_type));
+                    }
                 });
+                if circuits.take_diverged() {
+                    self.diverged.store(true, Ordering::Relaxed);
+                }
                 for (s, s64) in block.iter_mut().zip(f64_block.iter().copied()) {
                     *s = s64 as _;
                 }
             }
+            self.max_substeps.store(max_substeps, Ordering::Relaxed);
         }
         /*         self.stft.process_overlap_add(buffer, 1, |_ch, data| {
             let buflen = self.zero_stuff(data);
@@ -1,8 +1,47 @@
 #![allow(dead_code)]
-use std::f64::{consts::TAU, EPSILON};
+mod baxandall;
+mod bridged_t;
+mod diode_ladder;
+mod ladder;
+mod mfb;
+mod ms20;
+mod ota;
+mod phaser;
+mod rc_ladder;
+mod steiner_parker;
+mod svf;
+#[cfg(test)]
+mod test_utils;
+mod tone_stack;
+mod twin_t;
+mod vactrol;
+mod wah;
+
+pub use baxandall::{Baxandall, BaxandallState};
+pub use diode_ladder::{DiodeLadder, DiodeLadderState};
+pub use ladder::{MoogLadder, MoogLadderState};
+pub use mfb::{MfbBandpass, MfbLowpass, MfbState};
+pub use ms20::{Ms20Highpass, Ms20Lowpass};
+pub use ota::{OtaCascade, OtaCascadeState};
+pub use phaser::{Phaser, PhaserElement, PhaserLfo, PhaserState};
+pub use rc_ladder::{RcLadder, RcLadderState};
+pub use steiner_parker::SteinerParker;
+pub use svf::{Svf, SvfState};
+pub use tone_stack::{ToneStack, ToneStackComponents, ToneStackState, VoxToneStack};
+pub use vactrol::{LowpassGate, LowpassGateState, VactrolRc, VactrolRcState};
+pub use wah::{Wah, WahState};
+
+use std::f64::{
+    consts::{SQRT_2, TAU},
+    EPSILON,
+};
 
 use num_traits::Float;
 
+use crate::ode::{is_diverged, OdeSystem, Simulation, Solver};
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
 #[derive(Debug, Clone, Copy)]
 pub struct RcFilter {
     rc: f64,
@@ -18,9 +57,35 @@ impl RcFilter {
     pub fn set_fc(&mut self, fc: f64) {
         self.rc = (TAU * fc).recip();
     }
+}
+
+impl Lerp for RcFilter {
+    /// Interpolates the cutoff frequency rather than the time constant.
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            rc: self.rc.recip().lerp(&other.rc.recip(), t).recip(),
+        }
+    }
+}
+
+impl OdeSystem<1> for RcFilter {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 1]) -> [T; 1] {
+        [(u - x[0]) / T::from(self.rc).unwrap()]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 1]) -> f64 {
+        x[0]
+    }
+}
 
-    pub fn dv(&self, state: RcFilterState) -> f64 {
-        (state.v_in - state.v_c) / self.rc
+impl LinearCircuit<1> for RcFilter {
+    fn state_space(&self) -> StateSpace<1> {
+        StateSpace {
+            a: [[-self.rc.recip()]],
+            b: [self.rc.recip()],
+            c: [1.],
+            d: 0.,
+        }
     }
 }
 
@@ -28,17 +93,59 @@ impl RcFilter {
 pub struct RcFilterState {
     pub v_in: f64,
     pub v_c: f64,
+    sim: Simulation<RcFilter, 1>,
+    diverged: bool,
 }
 
 impl Default for RcFilterState {
     fn default() -> Self {
-        Self { v_c: 0., v_in: 0. }
+        Self::new(Solver::default())
     }
 }
 
 impl RcFilterState {
+    pub fn new(solver: Solver<1>) -> Self {
+        Self {
+            v_c: 0.,
+            v_in: 0.,
+            sim: Simulation::new(solver),
+            diverged: false,
+        }
+    }
+
+    pub fn set_solver(&mut self, solver: Solver<1>) {
+        self.sim.set_solver(solver);
+    }
+
+    /// Set the number of solver steps taken per call to [`Self::process`].
+    pub fn set_substeps(&mut self, substeps: usize) {
+        self.sim.set_substeps(substeps);
+    }
+
+    pub fn reset(&mut self) {
+        self.v_c = 0.;
+        self.sim.reset();
+    }
+
+    /// Number of solver substeps taken during the last call to [`Self::process`].
+    pub fn substeps(&self) -> usize {
+        self.sim.substeps()
+    }
+
+    /// Whether the simulation diverged and was reset since the last call to this method.
+    pub fn take_diverged(&mut self) -> bool {
+        std::mem::take(&mut self.diverged)
+    }
+
     pub fn process(&mut self, filter: &RcFilter, step: f64) -> f64 {
-        self.v_c += filter.dv(*self) * step;
+        let mut x = [self.v_c];
+        self.sim.process(filter, self.v_in, &mut x, step);
+        if is_diverged(&x) {
+            self.reset();
+            self.diverged = true;
+            return 0.;
+        }
+        self.v_c = x[0];
         self.v_c
     }
 }
@@ -68,25 +175,145 @@ impl ActiveLpf {
     }
 }
 
+impl Lerp for ActiveLpf {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            rc: self.rc.lerp(&other.rc, t),
+            vcc: self.vcc.lerp(&other.vcc, t),
+            amp: self.amp.lerp(&other.amp, t),
+        }
+    }
+}
+
+impl OdeSystem<1> for ActiveLpf {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 1]) -> [T; 1] {
+        self.rc.derivative(u, x)
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 1]) -> f64 {
+        clamp(-self.vcc, self.vcc, self.amp / (1. + self.amp) * x[0])
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 pub struct ActiveLpfState {
     rc: RcFilterState,
 }
 
 impl ActiveLpfState {
+    pub fn new(solver: Solver<1>) -> Self {
+        Self {
+            rc: RcFilterState::new(solver),
+        }
+    }
+
     pub fn set_v_in(&mut self, v_in: f64) {
         self.rc.v_in = v_in;
     }
+
+    pub fn set_solver(&mut self, solver: Solver<1>) {
+        self.rc.set_solver(solver);
+    }
+
+    pub fn set_substeps(&mut self, substeps: usize) {
+        self.rc.set_substeps(substeps);
+    }
+
+    pub fn reset(&mut self) {
+        self.rc.reset();
+    }
+
+    pub fn substeps(&self) -> usize {
+        self.rc.substeps()
+    }
+
+    /// Whether the simulation diverged and was reset since the last call to this method.
+    pub fn take_diverged(&mut self) -> bool {
+        self.rc.take_diverged()
+    }
 }
 
 impl ActiveLpfState {
     pub fn process(&mut self, filter: &ActiveLpf, step: f64) -> f64 {
         self.rc.process(&filter.rc, step);
-        clamp(
-            -filter.vcc,
-            filter.vcc,
-            filter.amp / (1. + filter.amp) * self.rc.v_c,
-        )
+        filter.output(self.rc.v_in, &[self.rc.v_c])
+    }
+}
+
+/// Zero-delay feedback (topology-preserving transform) simulation of [`ActiveLpf`].
+///
+/// The RC stage is a trapezoidal integrator whose feedback is solved within the same sample, with
+/// its gain prewarped so that the cutoff matches the analog one. Its state stays consistent when
+/// the cutoff is modulated quickly, as no coefficient depends on past values of the cutoff.
+#[derive(Debug, Clone, Copy)]
+pub struct ActiveLpfZdfState {
+    pub v_in: f64,
+    /// Integrator state, holding twice the capacitor voltage minus the last integrator input.
+    s: f64,
+    substeps: usize,
+    last: Option<ActiveLpf>,
+    diverged: bool,
+}
+
+impl Default for ActiveLpfZdfState {
+    fn default() -> Self {
+        Self {
+            v_in: 0.,
+            s: 0.,
+            substeps: 1,
+            last: None,
+            diverged: false,
+        }
+    }
+}
+
+impl ActiveLpfZdfState {
+    pub fn set_v_in(&mut self, v_in: f64) {
+        self.v_in = v_in;
+    }
+
+    /// Set the number of integrator steps taken per call to [`Self::process`].
+    pub fn set_substeps(&mut self, substeps: usize) {
+        self.substeps = substeps.max(1);
+    }
+
+    pub fn reset(&mut self) {
+        self.s = 0.;
+        self.last = None;
+    }
+
+    /// Number of integrator steps taken during the last call to [`Self::process`].
+    pub fn substeps(&self) -> usize {
+        self.substeps
+    }
+
+    /// Whether the simulation diverged and was reset since the last call to this method.
+    pub fn take_diverged(&mut self) -> bool {
+        std::mem::take(&mut self.diverged)
+    }
+
+    pub fn process(&mut self, filter: &ActiveLpf, step: f64) -> f64 {
+        let h = step / self.substeps as f64;
+        let from = self.last.unwrap_or(*filter);
+        let mut v_c = 0.;
+        for i in 1..=self.substeps {
+            let rc = from.rc.lerp(&filter.rc, i as f64 / self.substeps as f64).rc;
+            // tan(pi fc h), kept below Nyquist where the prewarping diverges
+            let g = (h / (2. * rc)).min(1.5).tan();
+            let v = (self.v_in - self.s) * g / (1. + g);
+            v_c = v + self.s;
+            self.s = v_c + v;
+        }
+        self.last = Some(*filter);
+        if is_diverged(&[self.s]) {
+            self.reset();
+            self.diverged = true;
+            return 0.;
+        }
+        // The op-amp follower with open-loop gain `amp` satisfies y = clamp(amp (v_c - y)). As the
+        // clamp is monotonic, its instantaneous solution is the clamped linear solution, which is
+        // what the output of the ODE model computes.
+        filter.output(self.v_in, &[v_c])
     }
 }
 
@@ -102,9 +329,15 @@ fn clamp<T: Float>(a: T, b: T, x: T) -> T {
     }
 } */
 
+/// Equal-component Sallen-Key lowpass: two resistors `R` in series from the input to the
+/// non-inverting input of the op-amp, `C1` from their junction to the output, `C2` from the
+/// op-amp input to ground. The op-amp is wired as an amplifier of gain `k`, which sets the
+/// resonance, and saturates at its `vcc` rails.
+#[derive(Debug, Clone, Copy)]
 pub struct SallenKey {
     rc: f64,
     k: f64,
+    pub vcc: f64,
 }
 
 impl SallenKey {
@@ -112,6 +345,7 @@ impl SallenKey {
         Self {
             rc: Self::get_rc(fc),
             k: Self::get_k(q),
+            vcc: 12.,
         }
     }
 
@@ -127,23 +361,550 @@ impl SallenKey {
         (TAU * fc).recip()
     }
 
+    /// With equal components, `Q = 1 / (3 - k)`; the filter self-oscillates when `k` reaches 3.
     fn get_k(q: f64) -> f64 {
         3. - q.max(EPSILON).recip()
     }
+
+    fn v_out<T: Float>(&self, v_c2: T) -> T {
+        saturate(self.vcc, T::from(self.k).unwrap() * v_c2)
+    }
+}
+
+impl Lerp for SallenKey {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            rc: self.rc.recip().lerp(&other.rc.recip(), t).recip(),
+            k: self.k.lerp(&other.k, t),
+            vcc: self.vcc.lerp(&other.vcc, t),
+        }
+    }
+}
+
+/// The state holds the voltages across `C1` (from the resistor junction to the output) and `C2`.
+impl OdeSystem<2> for SallenKey {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 2]) -> [T; 2] {
+        let [v_c1, v_c2] = *x;
+        let rc = T::from(self.rc).unwrap();
+        let two = T::from(2).unwrap();
+        // Voltage at the junction of the two resistors, bootstrapped by the output through C1
+        let v_a = v_c1 + self.v_out(v_c2);
+        let dv_c1 = (u - two * v_a + v_c2) / rc;
+        let dv_c2 = (v_a - v_c2) / rc;
+        [dv_c1, dv_c2]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 2]) -> f64 {
+        self.v_out(x[1])
+    }
+}
+
+/// Small-signal model, ignoring the op-amp saturation.
+impl LinearCircuit<2> for SallenKey {
+    fn state_space(&self) -> StateSpace<2> {
+        let g = self.rc.recip();
+        StateSpace {
+            a: [[-2. * g, (1. - 2. * self.k) * g], [g, (self.k - 1.) * g]],
+            b: [g, 0.],
+            c: [0., self.k],
+            d: 0.,
+        }
+    }
+}
+
+/// Equal-component Sallen-Key highpass, with the resistors and capacitors of [`SallenKey`]
+/// swapped: `C1` and `C2` in series from the input to the op-amp, `R1` from their junction to the
+/// output, `R2` from the op-amp input to ground.
+#[derive(Debug, Clone, Copy)]
+pub struct SallenKeyHighpass {
+    rc: f64,
+    k: f64,
+    pub vcc: f64,
+}
+
+impl SallenKeyHighpass {
+    pub fn new(fc: f64, q: f64) -> Self {
+        Self {
+            rc: SallenKey::get_rc(fc),
+            k: SallenKey::get_k(q),
+            vcc: 12.,
+        }
+    }
+
+    pub fn set_fc(&mut self, fc: f64) {
+        self.rc = SallenKey::get_rc(fc);
+    }
+
+    pub fn set_q(&mut self, q: f64) {
+        self.k = SallenKey::get_k(q);
+    }
+}
+
+impl Lerp for SallenKeyHighpass {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            rc: self.rc.recip().lerp(&other.rc.recip(), t).recip(),
+            k: self.k.lerp(&other.k, t),
+            vcc: self.vcc.lerp(&other.vcc, t),
+        }
+    }
 }
 
-pub struct SallenKeyState {
+/// The state holds the voltages across `C1` (from the input to the junction) and `C2`.
+impl OdeSystem<2> for SallenKeyHighpass {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 2]) -> [T; 2] {
+        let [v_c1, v_c2] = *x;
+        let rc = T::from(self.rc).unwrap();
+        let v_a = u - v_c1;
+        let v_b = v_a - v_c2;
+        let v_out = saturate(self.vcc, T::from(self.k).unwrap() * v_b);
+        // C1 feeds both C2 (which discharges into R2) and R1 to the output
+        let dv_c1 = (v_b + v_a - v_out) / rc;
+        let dv_c2 = v_b / rc;
+        [dv_c1, dv_c2]
+    }
+
+    fn output(&self, u: f64, x: &[f64; 2]) -> f64 {
+        saturate(self.vcc, self.k * (u - x[0] - x[1]))
+    }
+}
+
+/// Small-signal model, ignoring the op-amp saturation.
+impl LinearCircuit<2> for SallenKeyHighpass {
+    fn state_space(&self) -> StateSpace<2> {
+        let (g, k) = (self.rc.recip(), self.k);
+        StateSpace {
+            a: [[(k - 2.) * g, (k - 1.) * g], [-g, -g]],
+            b: [(2. - k) * g, g],
+            c: [-k, -k],
+            d: k,
+        }
+    }
+}
+
+/// Equal-component Sallen-Key bandpass: `R1` from the input to a junction loaded by `C1` to
+/// ground and `R2` to the output, then `C2` to the op-amp input, loaded by `R3` to ground.
+///
+/// The center frequency is `√2 / (2π rc)`, and `Q = √2 / (4 - k)`.
+#[derive(Debug, Clone, Copy)]
+pub struct SallenKeyBandpass {
+    rc: f64,
+    k: f64,
+    pub vcc: f64,
+}
+
+impl SallenKeyBandpass {
+    pub fn new(fc: f64, q: f64) -> Self {
+        Self {
+            rc: Self::get_rc(fc),
+            k: Self::get_k(q),
+            vcc: 12.,
+        }
+    }
+
+    pub fn set_fc(&mut self, fc: f64) {
+        self.rc = Self::get_rc(fc);
+    }
+
+    pub fn set_q(&mut self, q: f64) {
+        self.k = Self::get_k(q);
+    }
+
+    fn get_rc(fc: f64) -> f64 {
+        SQRT_2 * SallenKey::get_rc(fc)
+    }
+
+    fn get_k(q: f64) -> f64 {
+        4. - SQRT_2 / q.max(f64::EPSILON)
+    }
+}
+
+impl Lerp for SallenKeyBandpass {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            rc: self.rc.recip().lerp(&other.rc.recip(), t).recip(),
+            k: self.k.lerp(&other.k, t),
+            vcc: self.vcc.lerp(&other.vcc, t),
+        }
+    }
+}
+
+/// The state holds the voltages across `C1` (the junction voltage) and `C2`.
+impl OdeSystem<2> for SallenKeyBandpass {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 2]) -> [T; 2] {
+        let [v_a, v_c2] = *x;
+        let rc = T::from(self.rc).unwrap();
+        let v_b = v_a - v_c2;
+        let v_out = saturate(self.vcc, T::from(self.k).unwrap() * v_b);
+        // C2 carries the current flowing into R3
+        let dv_c2 = v_b / rc;
+        let dv_a = (u - v_a - v_b - (v_a - v_out)) / rc;
+        [dv_a, dv_c2]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 2]) -> f64 {
+        saturate(self.vcc, self.k * (x[0] - x[1]))
+    }
+}
+
+/// Small-signal model, ignoring the op-amp saturation.
+impl LinearCircuit<2> for SallenKeyBandpass {
+    fn state_space(&self) -> StateSpace<2> {
+        let (g, k) = (self.rc.recip(), self.k);
+        StateSpace {
+            a: [[(k - 3.) * g, (1. - k) * g], [g, -g]],
+            b: [g, 0.],
+            c: [k, -k],
+            d: 0.,
+        }
+    }
+}
+
+/// Op-amp output stage, clipping at the `vcc` rails.
+fn saturate<T: Float>(vcc: f64, x: T) -> T {
+    let vcc = T::from(vcc).unwrap();
+    clamp(-vcc, vcc, x)
+}
+
+/// State of any of the Sallen-Key topologies, all of which have two capacitors.
+pub type SallenKeyState<F = SallenKey> = CircuitState<F, 2>;
+
+/// State of a circuit `F` with `N` state variables, simulated with the solver it was given and
+/// reset when it diverges.
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitState<F, const N: usize> {
     pub v_in: f64,
-    last_v_c: f64,
-    v_c: f64,
+    x: [f64; N],
+    sim: Simulation<F, N>,
+    diverged: bool,
 }
 
-impl SallenKeyState {
-    pub fn process(&mut self, filter: &SallenKey, step: f64) -> f64 {
-        let dv_c = step * (self.last_v_c - self.v_c);
-        let den = filter.rc * filter.rc * dv_c / self.v_c / self.v_c
-            + filter.rc * (3. + filter.k) * dv_c / self.v_c
-            + 1.;
-        filter.k / den
+impl<F, const N: usize> Default for CircuitState<F, N> {
+    fn default() -> Self {
+        Self::new(Solver::default())
+    }
+}
+
+impl<F, const N: usize> CircuitState<F, N> {
+    pub fn new(solver: Solver<N>) -> Self {
+        Self {
+            v_in: 0.,
+            x: [0.; N],
+            sim: Simulation::new(solver),
+            diverged: false,
+        }
+    }
+
+    pub fn set_v_in(&mut self, v_in: f64) {
+        self.v_in = v_in;
+    }
+
+    pub fn set_solver(&mut self, solver: Solver<N>) {
+        self.sim.set_solver(solver);
+    }
+
+    /// Set the number of solver steps taken per call to [`Self::process`].
+    pub fn set_substeps(&mut self, substeps: usize) {
+        self.sim.set_substeps(substeps);
+    }
+
+    pub fn substeps(&self) -> usize {
+        self.sim.substeps()
+    }
+
+    pub fn reset(&mut self) {
+        self.x = [0.; N];
+        self.sim.reset();
+    }
+
+    /// Whether the simulation diverged and was reset since the last call to this method.
+    pub fn take_diverged(&mut self) -> bool {
+        std::mem::take(&mut self.diverged)
+    }
+}
+
+impl<F: OdeSystem<N> + Lerp + Copy, const N: usize> CircuitState<F, N> {
+    pub fn process(&mut self, filter: &F, step: f64) -> f64 {
+        let mut x = self.x;
+        self.sim.process(filter, self.v_in, &mut x, step);
+        if is_diverged(&x) {
+            self.reset();
+            self.diverged = true;
+            return 0.;
+        }
+        self.x = x;
+        filter.output(self.v_in, &x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use approx::{assert_abs_diff_eq, assert_relative_eq};
+
+    use crate::ode::{finite_difference_jacobian, Exponential, OdeSystem, Solver, Trapezoidal};
+
+    use super::{
+        ActiveLpf, ActiveLpfState, ActiveLpfZdfState, RcFilter, RcFilterState, SallenKey,
+        SallenKeyBandpass, SallenKeyHighpass, SallenKeyState,
+    };
+    use crate::state_space::LinearCircuit;
+
+    use super::test_utils::measure_gain;
+
+    #[test]
+    fn test_active_lpf_zdf_response() {
+        let step = (4. * 44100f64).recip();
+        let mut filter = ActiveLpf::new(1e3);
+        filter.set_amp(1e6);
+        for fc in [100., 1e3, 10e3, 30e3] {
+            filter.set_fc(fc);
+            // The bilinear transform compresses the response close to Nyquist
+            for freq in [fc / 4., fc, 2. * fc]
+                .into_iter()
+                .filter(|&f| f <= 0.25 / step)
+            {
+                let mut state = ActiveLpfZdfState::default();
+                let gain = measure_gain(freq, step, |x| {
+                    state.set_v_in(x);
+                    state.process(&filter, step)
+                });
+                let analog = f64::hypot(1., freq / fc).recip();
+                // Prewarping makes the response exact at the cutoff
+                let max_relative = if freq == fc { 1e-5 } else { 0.05 };
+                assert_relative_eq!(gain, analog, max_relative = max_relative);
+            }
+        }
+    }
+
+    #[test]
+    fn test_active_lpf_zdf_clamp() {
+        let step = (4. * 44100f64).recip();
+        let filter = ActiveLpf::new(1e3);
+        let mut state = ActiveLpfZdfState::default();
+        state.set_v_in(100.);
+        let mut out = 0.;
+        for _ in 0..1000 {
+            out = state.process(&filter, step);
+        }
+        assert_eq!(out, filter.vcc);
+    }
+
+    #[test]
+    fn test_rc_exponential_exact() {
+        // Step response is exact whatever the step size, even with the cutoff above Nyquist
+        let fc = 30e3;
+        let rc = (TAU * fc).recip();
+        let filter = RcFilter::new(fc);
+        for step in [1e-6, rc, 10. * rc] {
+            let mut state = RcFilterState::new(Solver::Exponential(Exponential::default()));
+            state.v_in = 1.;
+            for n in 1..=20 {
+                let v_c = state.process(&filter, step);
+                let t = n as f64 * step;
+                assert_abs_diff_eq!(v_c, 1. - f64::exp(-t / rc), epsilon = 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_jacobian_matches_finite_differences() {
+        let rc = RcFilter::new(1e3);
+        let x = [0.3];
+        assert_relative_eq!(
+            rc.jacobian(1., &x)[0][0],
+            finite_difference_jacobian(&rc, 1., &x)[0][0],
+            max_relative = 1e-6
+        );
+
+        let lpf = ActiveLpf::new(1e3);
+        assert_relative_eq!(
+            lpf.jacobian(1., &x)[0][0],
+            finite_difference_jacobian(&lpf, 1., &x)[0][0],
+            max_relative = 1e-6
+        );
+    }
+
+    #[test]
+    fn test_active_lpf_trapezoidal_stable_at_high_fc() {
+        // 30 kHz cutoff at 4x oversampling of 44.1 kHz puts the step above the RC time constant
+        let step = (4. * 44100f64).recip();
+        let mut filter = ActiveLpf::new(30e3);
+        filter.set_amp(10.);
+        let mut state = ActiveLpfState::new(Solver::Trapezoidal(Trapezoidal::default()));
+        state.set_v_in(100.);
+        let mut out = 0.;
+        for _ in 0..1000 {
+            out = state.process(&filter, step);
+            assert!(out.is_finite() && out.abs() <= filter.vcc);
+        }
+        assert_eq!(out, filter.vcc);
+    }
+
+    #[test]
+    fn test_divergence_recovery() {
+        // Forward Euler with the step far above the time constant blows up
+        let step = (4. * 44100f64).recip();
+        let filter = ActiveLpf::new(100e3);
+        let mut state = ActiveLpfState::default();
+        state.set_v_in(1.);
+        let mut diverged = false;
+        for _ in 0..1000 {
+            let out = state.process(&filter, step);
+            assert!(out.is_finite() && out.abs() <= filter.vcc);
+            diverged |= state.take_diverged();
+        }
+        assert!(diverged);
+        assert!(!state.take_diverged());
+
+        // A single bad input sample doesn't poison the state
+        let filter = SallenKey::new(1e3, 0.707);
+        let mut state = SallenKeyState::default();
+        state.set_v_in(f64::NAN);
+        assert_eq!(state.process(&filter, step), 0.);
+        assert!(state.take_diverged());
+        state.set_v_in(1.);
+        for _ in 0..100 {
+            assert!(state.process(&filter, step).is_finite());
+        }
+        assert!(!state.take_diverged());
+    }
+
+    #[test]
+    fn test_substeps() {
+        // Substeps make forward Euler stable again where it diverged in the test above
+        let step = (4. * 44100f64).recip();
+        let filter = ActiveLpf::new(100e3);
+        let mut state = ActiveLpfState::default();
+        state.set_substeps(4);
+        state.set_v_in(1.);
+        for _ in 0..1000 {
+            state.process(&filter, step);
+            assert_eq!(state.substeps(), 4);
+            assert!(!state.take_diverged());
+        }
+
+        // Cutoff changes are spread across the substeps
+        let step = 1e-4;
+        let mut rc = RcFilterState::new(Solver::Exponential(Exponential::default()));
+        rc.set_substeps(2);
+        rc.v_in = 1.;
+        rc.process(&RcFilter::new(1e3), step);
+        let v_c = rc.process(&RcFilter::new(3e3), step);
+        let after_first = 1. - f64::exp(-TAU * 1e3 * step);
+        let expected = 1. - (1. - after_first) * f64::exp(-TAU * 5e3 * step / 2.);
+        assert_abs_diff_eq!(v_c, expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_sallen_key_response() {
+        let step = (4. * 44100f64).recip();
+        let fc = 1e3;
+        for q in [0.5, std::f64::consts::FRAC_1_SQRT_2, 2., 8.] {
+            let filter = SallenKey::new(fc, q);
+            let k = 3. - q.recip();
+            let gain = |freq: f64| {
+                let mut state = SallenKeyState::new(Solver::Rk4);
+                // Small signal, keeping the resonance peak away from the rails
+                measure_gain(freq, step, |x| {
+                    state.set_v_in(0.1 * x);
+                    10. * state.process(&filter, step)
+                }) / k
+            };
+            // Analytic response of k / (s² rc² + s rc / Q + 1), normalized by its DC gain
+            let analog = |freq: f64| {
+                let w = freq / fc;
+                f64::hypot(1. - w * w, w / q).recip()
+            };
+
+            assert_relative_eq!(gain(fc), q, max_relative = 1e-3);
+            if q == std::f64::consts::FRAC_1_SQRT_2 {
+                // Butterworth: -3 dB at the cutoff
+                assert_relative_eq!(gain(fc), analog(fc), max_relative = 1e-3);
+                assert_relative_eq!(20. * gain(fc).log10(), -3.0103, max_relative = 1e-3);
+            }
+            if q > std::f64::consts::FRAC_1_SQRT_2 {
+                let f_peak = fc * f64::sqrt(1. - 0.5 / (q * q));
+                let peak = q / f64::sqrt(1. - 0.25 / (q * q));
+                assert_relative_eq!(analog(f_peak), peak, max_relative = 1e-9);
+                assert_relative_eq!(gain(f_peak), peak, max_relative = 1e-3);
+            }
+            for freq in [fc / 4., 4. * fc] {
+                assert_relative_eq!(gain(freq), analog(freq), max_relative = 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sallen_key_saturation() {
+        let step = (4. * 44100f64).recip();
+        let filter = SallenKey::new(1e3, 8.);
+        let mut state = SallenKeyState::new(Solver::Trapezoidal(Trapezoidal::default()));
+        let mut max = 0f64;
+        for n in 0..10000 {
+            state.set_v_in(if (n / 100) % 2 == 0 { 10. } else { -10. });
+            let out = state.process(&filter, step);
+            assert!(out.abs() <= filter.vcc);
+            max = max.max(out.abs());
+        }
+        assert_eq!(max, filter.vcc);
+        assert!(!state.take_diverged());
+    }
+
+    #[test]
+    fn test_sallen_key_highpass_bandpass() {
+        let step = (4. * 44100f64).recip();
+        let fc = 1e3;
+        for q in [std::f64::consts::FRAC_1_SQRT_2, 4.] {
+            let hp = SallenKeyHighpass::new(fc, q);
+            let bp = SallenKeyBandpass::new(fc, q);
+            let k_hp = 3. - q.recip();
+            let k_bp = 4. - std::f64::consts::SQRT_2 / q;
+            // Analytic responses of k s² rc² / (s² rc² + s rc / Q + 1) and of
+            // k s rc / (s² rc² + (4 - k) s rc + 2), which with `s` normalized to the natural
+            // frequency `√2 / rc` is the bandpass (k / √2) s / (s² + s / Q + 1)
+            let analog_hp = |freq: f64| {
+                let w = freq / fc;
+                k_hp * w * w / f64::hypot(1. - w * w, w / q)
+            };
+            let analog_bp = |freq: f64| {
+                let w = freq / fc;
+                k_bp / std::f64::consts::SQRT_2 * w / f64::hypot(1. - w * w, w / q)
+            };
+            assert_relative_eq!(analog_hp(fc), k_hp * q, max_relative = 1e-9);
+            assert_relative_eq!(analog_bp(fc), k_bp * q / std::f64::consts::SQRT_2);
+
+            for freq in [fc / 4., fc, 4. * fc] {
+                let hp_ss = hp.state_space().frequency_response(freq).norm();
+                let bp_ss = bp.state_space().frequency_response(freq).norm();
+                assert_relative_eq!(hp_ss, analog_hp(freq), max_relative = 1e-9);
+                assert_relative_eq!(bp_ss, analog_bp(freq), max_relative = 1e-9);
+
+                let mut state = SallenKeyState::new(Solver::Rk4);
+                let gain = measure_gain(freq, step, |x| {
+                    state.set_v_in(0.1 * x);
+                    10. * state.process(&hp, step)
+                });
+                // The input feeds through to the output but is held over each step, which shows
+                // as a relative error in the stopband
+                assert_relative_eq!(gain, hp_ss, max_relative = 0.05);
+                let mut state = SallenKeyState::new(Solver::Rk4);
+                let gain = measure_gain(freq, step, |x| {
+                    state.set_v_in(0.1 * x);
+                    10. * state.process(&bp, step)
+                });
+                assert_relative_eq!(gain, bp_ss, max_relative = 1e-3);
+            }
+        }
+
+        // Both saturate at the rails like the lowpass
+        let hp = SallenKeyHighpass::new(1e3, 8.);
+        let mut state = SallenKeyState::new(Solver::Trapezoidal(Trapezoidal::default()));
+        for n in 0..10000 {
+            state.set_v_in(if (n / 100) % 2 == 0 { 10. } else { -10. });
+            assert!(state.process(&hp, step).abs() <= hp.vcc);
+        }
+        assert!(!state.take_diverged());
     }
 }
@@ -0,0 +1,240 @@
+//! Active Baxandall bass and treble control.
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::tone_stack::Taper;
+use super::CircuitState;
+
+/// State of the tone control, holding the voltages across its four capacitors.
+pub type BaxandallState = CircuitState<Baxandall, 4>;
+
+/// Baxandall tone control around an inverting op-amp, made of a bass and a treble network which
+/// both bridge the input and the output and meet at the inverting input.
+///
+/// In the bass network, `r_in` goes from the input and from the output to either end of the bass
+/// pot, with `c_bass` across each half of the pot and the wiper on the inverting input. In the
+/// treble network, `c_treble` goes from the input and from the output to either end of the treble
+/// pot, whose wiper goes to the inverting input through `r_wiper`. Turning a pot towards the input
+/// side boosts its band, up to `1 + r_bass / r_in` for the bass and `1 + r_in / r_wiper` for the
+/// treble, and turning it the other way cuts it by as much. With both pots centered, the response
+/// is flat.
+///
+/// The op-amp is ideal, so that the output is inverted.
+#[derive(Debug, Clone, Copy)]
+pub struct Baxandall {
+    pub taper: Taper,
+    pub r_bass: f64,
+    pub r_in: f64,
+    pub c_bass: f64,
+    pub r_treble: f64,
+    pub r_wiper: f64,
+    pub c_treble: f64,
+    bass: f64,
+    treble: f64,
+}
+
+impl Default for Baxandall {
+    fn default() -> Self {
+        Self {
+            taper: Taper::Linear,
+            r_bass: 100e3,
+            r_in: 10e3,
+            c_bass: 22e-9,
+            r_treble: 100e3,
+            r_wiper: 3.3e3,
+            c_treble: 2.2e-9,
+            bass: 0.5,
+            treble: 0.5,
+        }
+    }
+}
+
+impl Baxandall {
+    /// Set the bass pot position, from full cut at 0 to full boost at 1.
+    pub fn set_bass(&mut self, position: f64) {
+        self.bass = position;
+    }
+
+    /// Set the treble pot position, from full cut at 0 to full boost at 1.
+    pub fn set_treble(&mut self, position: f64) {
+        self.treble = position;
+    }
+
+    /// Fractions of the bass and treble pots between their input side and their wiper.
+    pub fn pot_fractions(&self) -> [f64; 2] {
+        [self.bass, self.treble].map(|p| 1. - self.taper.fraction(p))
+    }
+
+    /// Conductances of the input and output halves of the bass pot, then of the treble pot.
+    fn conductances<T: Float>(&self) -> [T; 4] {
+        let [b, t] = self.pot_fractions();
+        [
+            b * self.r_bass,
+            (1. - b) * self.r_bass,
+            t * self.r_treble,
+            (1. - t) * self.r_treble,
+        ]
+        .map(|r| T::from(r.recip()).unwrap())
+    }
+
+    /// Output voltage and voltage at the treble wiper. The op-amp sets the output so that the
+    /// current coming into the inverting input from the treble wiper cancels the one from the
+    /// bass network, all of which goes through the two `r_in`.
+    fn nodes<T: Float>(&self, u: T, x: &[T; 4]) -> [T; 2] {
+        let [v_c1, v_c2, v_c3, v_c4] = *x;
+        let [_, _, g_t1, g_t2] = self.conductances::<T>();
+        let g_in = T::from(self.r_in.recip()).unwrap();
+        let g_w = T::from(self.r_wiper.recip()).unwrap();
+        // The treble wiper sits at a weighted average of both ends of the pot, which lets through
+        // a fraction `k` of the current each end would send straight into the inverting input
+        let k = g_w / (g_t1 + g_t2 + g_w);
+        let v_out =
+            (-(u - v_c1 - v_c2) * g_in - k * ((u - v_c3) * g_t1 - v_c4 * g_t2)) / (g_in + k * g_t2);
+        let v_wiper = k * ((u - v_c3) * g_t1 + (v_out - v_c4) * g_t2) / g_w;
+        [v_out, v_wiper]
+    }
+}
+
+impl Lerp for Baxandall {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            bass: self.bass.lerp(&other.bass, t),
+            treble: self.treble.lerp(&other.treble, t),
+            ..*other
+        }
+    }
+}
+
+/// The state holds the voltages across the input and output halves of the bass pot, then across
+/// the input and output treble capacitors, each from the input or output side.
+impl OdeSystem<4> for Baxandall {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 4]) -> [T; 4] {
+        let [v_c1, v_c2, v_c3, v_c4] = *x;
+        let [g_a, g_b, g_t1, g_t2] = self.conductances::<T>();
+        let g_in = T::from(self.r_in.recip()).unwrap();
+        let [c_bass, c_treble] = [self.c_bass, self.c_treble].map(|c| T::from(c).unwrap());
+        let [v_out, v_wiper] = self.nodes(u, x);
+        [
+            ((u - v_c1) * g_in - v_c1 * g_a) / c_bass,
+            ((v_out - v_c2) * g_in - v_c2 * g_b) / c_bass,
+            (u - v_c3 - v_wiper) * g_t1 / c_treble,
+            (v_out - v_c4 - v_wiper) * g_t2 / c_treble,
+        ]
+    }
+
+    fn output(&self, u: f64, x: &[f64; 4]) -> f64 {
+        self.nodes(u, x)[0]
+    }
+}
+
+/// The network is linear, so its state-space model is read off the derivative and the output.
+impl LinearCircuit<4> for Baxandall {
+    fn state_space(&self) -> StateSpace<4> {
+        let mut a = [[0.; 4]; 4];
+        let mut c = [0.; 4];
+        for j in 0..4 {
+            let mut x = [0.; 4];
+            x[j] = 1.;
+            let dx = self.derivative(0., &x);
+            for i in 0..4 {
+                a[i][j] = dx[i];
+            }
+            c[j] = self.output(0., &x);
+        }
+        StateSpace {
+            a,
+            b: self.derivative(1., &[0.; 4]),
+            c,
+            d: self.output(1., &[0.; 4]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::lpf::test_utils::{measure_gain, nodal_response, Element};
+    use crate::ode::{Exponential, Solver};
+    use crate::state_space::LinearCircuit;
+
+    use super::{Baxandall, BaxandallState};
+
+    /// Netlist of the tone control as drawn by P. J. Baxandall, "Negative-feedback tone control",
+    /// Wireless World, October 1952, with the bass network on the input and output nodes 1 and
+    /// 2 through the ends of the bass pot, 4 and 5, and the treble one through the ends of the
+    /// treble pot, 6 and 7, and its wiper, 8, both meeting at the inverting input, 3.
+    fn netlist(filter: &Baxandall) -> [Element; 12] {
+        let [b, t] = filter.pot_fractions();
+        [
+            Element::Resistor(1, 4, filter.r_in),
+            Element::Resistor(4, 3, b * filter.r_bass),
+            Element::Capacitor(4, 3, filter.c_bass),
+            Element::Resistor(3, 5, (1. - b) * filter.r_bass),
+            Element::Capacitor(3, 5, filter.c_bass),
+            Element::Resistor(5, 2, filter.r_in),
+            Element::Capacitor(1, 6, filter.c_treble),
+            Element::Resistor(6, 8, t * filter.r_treble),
+            Element::Resistor(8, 7, (1. - t) * filter.r_treble),
+            Element::Capacitor(7, 2, filter.c_treble),
+            Element::Resistor(8, 3, filter.r_wiper),
+            Element::OpAmp(0, 3, 2),
+        ]
+    }
+
+    #[test]
+    fn test_baxandall_response() {
+        let mut filter = Baxandall::default();
+        for (bass, treble) in [(0.5, 0.5), (1., 0.5), (0.2, 0.9), (0., 0.), (0.7, 1.)] {
+            filter.set_bass(bass);
+            filter.set_treble(treble);
+            let sys = filter.state_space();
+            for freq in [20., 100., 500., 2e3, 10e3] {
+                let expected = nodal_response(&netlist(&filter), 2, freq);
+                let response = sys.frequency_response(freq);
+                assert_relative_eq!(response.re, expected.re, epsilon = 1e-9);
+                assert_relative_eq!(response.im, expected.im, epsilon = 1e-9);
+            }
+        }
+
+        // Flat when centered
+        filter.set_bass(0.5);
+        filter.set_treble(0.5);
+        for freq in [20., 1e3, 20e3] {
+            let gain = filter.state_space().frequency_response(freq).norm();
+            assert_relative_eq!(gain, 1., max_relative = 1e-9);
+        }
+        // Boosting each band by as much as the resistor ratios allow, short of the bit of track
+        // left between the wiper and the end of the pot
+        filter.set_bass(1.);
+        let [b, _] = filter.pot_fractions();
+        let boost = (filter.r_in + (1. - b) * filter.r_bass) / (filter.r_in + b * filter.r_bass);
+        assert_relative_eq!(filter.state_space().dc_gain(), -boost, max_relative = 1e-9);
+        assert_relative_eq!(boost, 1. + filter.r_bass / filter.r_in, max_relative = 2e-2);
+        filter.set_bass(0.5);
+        filter.set_treble(1.);
+        let gain = filter.state_space().frequency_response(1e6).norm();
+        assert_relative_eq!(gain, 1. + filter.r_in / filter.r_wiper, max_relative = 5e-2);
+    }
+
+    #[test]
+    fn test_baxandall_simulation() {
+        let step = (4. * 44100f64).recip();
+        let mut filter = Baxandall::default();
+        filter.set_bass(0.9);
+        filter.set_treble(0.2);
+        for freq in [50., 1e3, 5e3] {
+            let expected = filter.state_space().frequency_response(freq).norm();
+            let mut state = BaxandallState::new(Solver::Exponential(Exponential::default()));
+            let gain = measure_gain(freq, step, |x| {
+                state.set_v_in(x);
+                state.process(&filter, step)
+            });
+            assert_relative_eq!(gain, expected, max_relative = 1e-2);
+        }
+    }
+}
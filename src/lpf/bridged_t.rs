@@ -0,0 +1,146 @@
+//! Bridged-T resonator, as in the TR-808 bass drum.
+
+use std::f64::consts::TAU;
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::CircuitState;
+
+/// State of the bridged-T resonator, which has two capacitors.
+pub type BridgedTState<F = BridgedT> = CircuitState<F, 2>;
+
+/// Inverting op-amp stage with a bridged-T network in its feedback path: `C1` and `C2` in series
+/// from the output to the inverting input, bridged by `R_b`, with `R_a` from the node between
+/// them to ground. The input current comes in through `R_in` to the inverting input.
+///
+/// With `G_a = 1 / R_a`, the response is
+/// `-(R_b / R_in) (s (C1 + C2) + G_a) / (s² R_b C1 C2 + s (C1 + C2) + G_a)`, which peaks around
+/// `fc` and rings for a decay time of `2 Q / (2π fc)` when struck with an impulse. The op-amp is
+/// ideal.
+#[derive(Debug, Clone, Copy)]
+pub struct BridgedT {
+    pub r_a: f64,
+    pub r_b: f64,
+    pub r_in: f64,
+    pub c1: f64,
+    pub c2: f64,
+}
+
+impl BridgedT {
+    pub fn new(r_a: f64, r_b: f64, r_in: f64, c1: f64, c2: f64) -> Self {
+        Self {
+            r_a,
+            r_b,
+            r_in,
+            c1,
+            c2,
+        }
+    }
+
+    /// Resonant frequency.
+    pub fn fc(&self) -> f64 {
+        (TAU * (self.r_a * self.r_b * self.c1 * self.c2).sqrt()).recip()
+    }
+
+    pub fn q(&self) -> f64 {
+        let (c1, c2) = (self.c1, self.c2);
+        (self.r_b / self.r_a).sqrt() * (c1 * c2).sqrt() / (c1 + c2)
+    }
+}
+
+impl Lerp for BridgedT {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            r_a: self.r_a.lerp(&other.r_a, t),
+            r_b: self.r_b.lerp(&other.r_b, t),
+            r_in: self.r_in.lerp(&other.r_in, t),
+            c1: self.c1.lerp(&other.c1, t),
+            c2: self.c2.lerp(&other.c2, t),
+        }
+    }
+}
+
+/// The state holds the voltages across `C1`, from the middle node to the output, and `C2`, from
+/// the inverting input to the middle node.
+impl OdeSystem<2> for BridgedT {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 2]) -> [T; 2] {
+        let [v_c1, v_c2] = *x;
+        let [g_a, g_b, g_in] = [self.r_a, self.r_b, self.r_in].map(|r| T::from(r.recip()).unwrap());
+        let (c1, c2) = (T::from(self.c1).unwrap(), T::from(self.c2).unwrap());
+        let v_out = v_c1 + v_c2;
+        // Whatever comes in through R_in and R_b has to leave the inverting input through C2
+        let i_c2 = -(u * g_in + v_out * g_b);
+        let i_c1 = v_c2 * g_a + i_c2;
+        [i_c1 / c1, i_c2 / c2]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 2]) -> f64 {
+        x[0] + x[1]
+    }
+}
+
+impl LinearCircuit<2> for BridgedT {
+    fn state_space(&self) -> StateSpace<2> {
+        let [g_a, g_b, g_in] = [self.r_a, self.r_b, self.r_in].map(f64::recip);
+        let (c1, c2) = (self.c1, self.c2);
+        StateSpace {
+            a: [[-g_b / c1, (g_a - g_b) / c1], [-g_b / c2, -g_b / c2]],
+            b: [-g_in / c1, -g_in / c2],
+            c: [1., 1.],
+            d: 0.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use approx::assert_relative_eq;
+
+    use crate::lpf::test_utils::ring;
+    use crate::ode::{Solver, Trapezoidal};
+    use crate::state_space::LinearCircuit;
+
+    use super::{BridgedT, BridgedTState};
+
+    #[test]
+    fn test_bridged_t_response() {
+        let filter = BridgedT::new(1e3, 400e3, 100e3, 22e-9, 22e-9);
+        let (fc, q) = (filter.fc(), filter.q());
+        assert_relative_eq!(q, 10.);
+        let sys = filter.state_space();
+        assert_relative_eq!(sys.dc_gain(), -4., max_relative = 1e-9);
+        // At resonance, the zero of the response lifts the peak above R_b / R_in
+        let w = TAU * fc;
+        let zero = f64::hypot(1e-3, w * 44e-9);
+        let pole = w * 44e-9;
+        assert_relative_eq!(
+            sys.frequency_response(fc).norm(),
+            4. * zero / pole,
+            max_relative = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_bridged_t_impulse() {
+        let step = (4. * 44100f64).recip();
+        let filter = BridgedT::new(1e3, 400e3, 100e3, 22e-9, 22e-9);
+        let (fc, q) = (filter.fc(), filter.q());
+        let mut state = BridgedTState::new(Solver::Trapezoidal(Trapezoidal::default()));
+        let ring = ring(step, 0.2, 1e-3, 1, |x| {
+            state.set_v_in(x);
+            state.process(&filter, step)
+        });
+
+        // Peaks come once per period of the damped ring, and its envelope decays exponentially
+        let damped = fc * f64::sqrt(1. - 0.25 / (q * q));
+        assert_relative_eq!(ring.freq, damped, max_relative = 1e-3);
+        let decay = -ring.rate.recip();
+        assert_relative_eq!(decay, 2. * q / (TAU * fc), max_relative = 1e-3);
+    }
+}
@@ -0,0 +1,187 @@
+//! Diode ladder lowpass, as found in the Roland TB-303.
+
+use std::f64::consts::{SQRT_2, TAU};
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::CircuitState;
+
+/// Four-pole diode ladder. Unlike the transistor ladder, the stages are not buffered from each
+/// other: each capacitor is charged by the diode pair below it and discharged by the one above,
+/// so that the poles interact. The current through each diode pair is the `tanh` of the voltage
+/// across it, and the top capacitor is half the value of the others.
+///
+/// The output of the top stage is fed back to the input through a coupling capacitor, which
+/// removes the resonance at low frequencies.
+#[derive(Debug, Clone, Copy)]
+pub struct DiodeLadder {
+    wc: f64,
+    resonance: f64,
+    w_coupling: f64,
+}
+
+impl DiodeLadder {
+    /// Feedback gain at which the filter self-oscillates. The interacting stages lose more gain
+    /// than four buffered ones at the frequency where their phase shift reaches -180°.
+    pub const SELF_OSCILLATION: f64 = 17.;
+
+    pub fn new(fc: f64, resonance: f64) -> Self {
+        Self {
+            wc: Self::get_wc(fc),
+            resonance,
+            w_coupling: TAU * 10.,
+        }
+    }
+
+    /// Set the frequency of the resonance peak, which sits above the cutoff of the stages.
+    pub fn set_fc(&mut self, fc: f64) {
+        self.wc = Self::get_wc(fc);
+    }
+
+    /// Set the feedback gain, which reaches self-oscillation at [`Self::SELF_OSCILLATION`].
+    pub fn set_resonance(&mut self, resonance: f64) {
+        self.resonance = resonance.max(0.);
+    }
+
+    /// Set the cutoff of the highpass formed by the coupling capacitor in the feedback path.
+    pub fn set_coupling_fc(&mut self, fc: f64) {
+        self.w_coupling = TAU * fc;
+    }
+
+    fn get_wc(fc: f64) -> f64 {
+        TAU * fc / SQRT_2
+    }
+}
+
+impl Lerp for DiodeLadder {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            wc: self.wc.lerp(&other.wc, t),
+            resonance: self.resonance.lerp(&other.resonance, t),
+            w_coupling: self.w_coupling.lerp(&other.w_coupling, t),
+        }
+    }
+}
+
+/// The state holds the normalized voltage of each capacitor of the ladder, then the voltage
+/// across the coupling capacitor.
+impl OdeSystem<5> for DiodeLadder {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 5]) -> [T; 5] {
+        let [y1, y2, y3, y4, v_coupling] = *x;
+        let wc = T::from(self.wc).unwrap();
+        let feedback = T::from(self.resonance).unwrap() * (y4 - v_coupling);
+        let i0 = (u - feedback - y1).tanh();
+        let i1 = (y1 - y2).tanh();
+        let i2 = (y2 - y3).tanh();
+        let i3 = (y3 - y4).tanh();
+        [
+            wc * (i0 - i1),
+            wc * (i1 - i2),
+            wc * (i2 - i3),
+            T::from(2).unwrap() * wc * i3,
+            T::from(self.w_coupling).unwrap() * (y4 - v_coupling),
+        ]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 5]) -> f64 {
+        x[3]
+    }
+}
+
+/// Small-signal model, around zero where every `tanh` is the identity.
+impl LinearCircuit<5> for DiodeLadder {
+    fn state_space(&self) -> StateSpace<5> {
+        let (g, k, h) = (self.wc, self.resonance, self.w_coupling);
+        StateSpace {
+            a: [
+                [-2. * g, g, 0., -k * g, k * g],
+                [g, -2. * g, g, 0., 0.],
+                [0., g, -2. * g, g, 0.],
+                [0., 0., 2. * g, -2. * g, 0.],
+                [0., 0., 0., h, -h],
+            ],
+            b: [g, 0., 0., 0., 0.],
+            c: [0., 0., 0., 1., 0.],
+            d: 0.,
+        }
+    }
+}
+
+/// State of the ladder, holding the capacitor voltage of each stage and of the coupling capacitor.
+pub type DiodeLadderState = CircuitState<DiodeLadder, 5>;
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::lpf::test_utils::{measure_gain, ring};
+    use crate::ode::Solver;
+    use crate::state_space::LinearCircuit;
+
+    use super::{DiodeLadder, DiodeLadderState};
+
+    #[test]
+    fn test_diode_ladder_resonance() {
+        let step = (4. * 44100f64).recip();
+        let fc = 1e3;
+        let filter = DiodeLadder::new(fc, 16.);
+        let sys = filter.state_space();
+
+        // The peak sits at the cutoff, well above the passband
+        let peak = sys.frequency_response(fc).norm();
+        for freq in [0.9 * fc, 1.1 * fc] {
+            assert!(sys.frequency_response(freq).norm() < peak);
+        }
+        assert!(peak > 10. * sys.frequency_response(fc / 20.).norm());
+        // A small sine wave keeps the diodes in their linear region
+        let mut state = DiodeLadderState::new(Solver::Rk4);
+        let gain = measure_gain(fc, step, |x| {
+            state.set_v_in(1e-3 * x);
+            state.process(&filter, step) / 1e-3
+        });
+        assert_relative_eq!(gain, peak, max_relative = 1e-2);
+
+        // After a short kick, the ringing dies out below the threshold and builds up above it
+        let kick = |resonance: f64| {
+            let filter = DiodeLadder::new(fc, resonance);
+            let mut state = DiodeLadderState::new(Solver::Rk4);
+            let ring = ring(step, 100. / fc, 1e-2 * step, 2, |x| {
+                state.set_v_in(x);
+                state.process(&filter, step)
+            });
+            ring.last / ring.first
+        };
+        assert!(kick(0.95 * DiodeLadder::SELF_OSCILLATION) < 0.1);
+        assert!(kick(1.05 * DiodeLadder::SELF_OSCILLATION) > 10.);
+    }
+
+    #[test]
+    fn test_diode_ladder_passband_loss() {
+        let step = (4. * 44100f64).recip();
+        let fc = 5e3;
+        let freq = fc / 100.;
+        // The feedback pulls the passband down by 1 + k, above the coupling capacitor cutoff
+        for k in [0., 8., 16.] {
+            let mut filter = DiodeLadder::new(fc, k);
+            filter.set_coupling_fc(1.);
+            let mut state = DiodeLadderState::new(Solver::Rk4);
+            let gain = measure_gain(freq, step, |x| {
+                state.set_v_in(1e-3 * x);
+                state.process(&filter, step) / 1e-3
+            });
+            assert_relative_eq!(
+                gain,
+                filter.state_space().frequency_response(freq).norm(),
+                max_relative = 1e-2
+            );
+            assert_relative_eq!(gain, (1. + k).recip(), max_relative = 0.02);
+        }
+        // Below it, the resonance no longer eats into the bass
+        let filter = DiodeLadder::new(fc, 16.);
+        assert_relative_eq!(filter.state_space().dc_gain(), 1., max_relative = 1e-9);
+    }
+}
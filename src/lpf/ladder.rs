@@ -0,0 +1,171 @@
+//! Transistor ladder lowpass, as found in Moog synthesizers.
+
+use std::f64::consts::TAU;
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::CircuitState;
+
+/// Four-pole transistor ladder. Each stage is a pair of transistors loaded by a capacitor, whose
+/// differential current is the `tanh` of the voltage across the pair, and the output of the last
+/// stage is fed back to the input with gain `resonance`.
+///
+/// Voltages are normalized to twice the thermal voltage of the transistors; `drive` scales the
+/// input into that range, and the output back out of it.
+#[derive(Debug, Clone, Copy)]
+pub struct MoogLadder {
+    wc: f64,
+    resonance: f64,
+    bass_compensation: f64,
+    pub drive: f64,
+}
+
+impl MoogLadder {
+    /// Loop gain at which the filter self-oscillates: each stage contributes 45° of phase shift
+    /// and a gain of `1/√2` at the cutoff, so the feedback reaches -180° with a gain of `1/4`.
+    pub const SELF_OSCILLATION: f64 = 4.;
+
+    pub fn new(fc: f64, resonance: f64) -> Self {
+        Self {
+            wc: TAU * fc,
+            resonance,
+            bass_compensation: 0.,
+            drive: 1.,
+        }
+    }
+
+    pub fn set_fc(&mut self, fc: f64) {
+        self.wc = TAU * fc;
+    }
+
+    /// Set the feedback gain, which reaches self-oscillation at [`Self::SELF_OSCILLATION`].
+    pub fn set_resonance(&mut self, resonance: f64) {
+        self.resonance = resonance.max(0.);
+    }
+
+    /// Amount of input added back in proportion to the resonance, between 0 and 1. The feedback
+    /// pulls the passband down by `1 + resonance`, which full compensation brings back to unity.
+    pub fn set_bass_compensation(&mut self, amount: f64) {
+        self.bass_compensation = amount.clamp(0., 1.);
+    }
+
+    fn input_gain(&self) -> f64 {
+        self.drive * (1. + self.bass_compensation * self.resonance)
+    }
+}
+
+impl Lerp for MoogLadder {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            wc: self.wc.lerp(&other.wc, t),
+            resonance: self.resonance.lerp(&other.resonance, t),
+            bass_compensation: self.bass_compensation.lerp(&other.bass_compensation, t),
+            drive: self.drive.lerp(&other.drive, t),
+        }
+    }
+}
+
+/// The state holds the normalized capacitor voltage of each stage.
+impl OdeSystem<4> for MoogLadder {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 4]) -> [T; 4] {
+        let wc = T::from(self.wc).unwrap();
+        let input = T::from(self.input_gain()).unwrap() * u;
+        let feedback = T::from(self.resonance).unwrap() * x[3];
+        let mut v_in = input - feedback;
+        let mut dx = [T::zero(); 4];
+        for i in 0..4 {
+            dx[i] = wc * (v_in.tanh() - x[i].tanh());
+            v_in = x[i];
+        }
+        dx
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 4]) -> f64 {
+        x[3] / self.drive
+    }
+}
+
+/// Small-signal model, around zero where every `tanh` is the identity.
+impl LinearCircuit<4> for MoogLadder {
+    fn state_space(&self) -> StateSpace<4> {
+        let wc = self.wc;
+        let mut a = [[0.; 4]; 4];
+        for i in 0..4 {
+            a[i][i] = -wc;
+            if i > 0 {
+                a[i][i - 1] = wc;
+            }
+        }
+        a[0][3] = -wc * self.resonance;
+        StateSpace {
+            a,
+            b: [wc * self.input_gain(), 0., 0., 0.],
+            c: [0., 0., 0., self.drive.recip()],
+            d: 0.,
+        }
+    }
+}
+
+/// State of the ladder, holding the capacitor voltage of each stage.
+pub type MoogLadderState = CircuitState<MoogLadder, 4>;
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::lpf::test_utils::ring;
+    use crate::ode::{Solver, Trapezoidal};
+    use crate::state_space::LinearCircuit;
+
+    use super::{MoogLadder, MoogLadderState};
+
+    #[test]
+    fn test_moog_ladder_passband() {
+        let step = (4. * 44100f64).recip();
+        for (resonance, compensation, expected) in [(0., 0., 1.), (2., 0., 1. / 3.), (2., 1., 1.)] {
+            let mut filter = MoogLadder::new(1e3, resonance);
+            filter.set_bass_compensation(compensation);
+            assert_relative_eq!(
+                filter.state_space().dc_gain(),
+                expected,
+                max_relative = 1e-9
+            );
+
+            let mut state = MoogLadderState::new(Solver::Rk4);
+            state.set_v_in(1e-3);
+            let mut out = 0.;
+            for _ in 0..10000 {
+                out = state.process(&filter, step);
+            }
+            assert_relative_eq!(out / 1e-3, expected, max_relative = 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_moog_ladder_self_oscillation() {
+        let step = (4. * 44100f64).recip();
+        let fc = 1e3;
+        // Ring left after a short kick, over 100 periods
+        let kick = |resonance: f64| {
+            let filter = MoogLadder::new(fc, resonance);
+            let mut state = MoogLadderState::new(Solver::Trapezoidal(Trapezoidal::default()));
+            ring(step, 100. / fc, step, 2, |x| {
+                state.set_v_in(x);
+                state.process(&filter, step)
+            })
+        };
+
+        let decaying = kick(0.95 * MoogLadder::SELF_OSCILLATION);
+        assert!(decaying.last < 0.1 * decaying.first);
+
+        // Above the threshold, the oscillation grows until the stages saturate, and then holds
+        let growing = kick(1.05 * MoogLadder::SELF_OSCILLATION);
+        assert!(growing.last > 10. * growing.first);
+        assert!(growing.last < 2.);
+        assert_relative_eq!(growing.freq, fc, max_relative = 0.05);
+    }
+}
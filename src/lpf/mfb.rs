@@ -0,0 +1,336 @@
+//! Multiple-feedback active filters.
+//!
+//! Both topologies are inverting, with the op-amp's non-inverting input grounded. The op-amp has
+//! a finite open-loop gain `amp` and clips at its `vcc` rails, like in [`super::ActiveLpf`]; its
+//! output is `clamp(-amp v_n)` with `v_n` the voltage at its inverting input, which for a given
+//! state has a closed form that stays consistent when the output clips.
+
+use std::f64::consts::TAU;
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::{clamp, CircuitState};
+
+/// State of the multiple-feedback filters, which have two capacitors.
+pub type MfbState<F = MfbLowpass> = CircuitState<F, 2>;
+
+/// Multiple-feedback lowpass: `R1` from the input to node `A`, which has `C1` to ground, `R2` to
+/// the output and `R3` to the inverting input, with `C2` from the output to the inverting input.
+#[derive(Debug, Clone, Copy)]
+pub struct MfbLowpass {
+    pub r1: f64,
+    pub r2: f64,
+    pub r3: f64,
+    pub c1: f64,
+    pub c2: f64,
+    pub amp: f64,
+    pub vcc: f64,
+}
+
+impl MfbLowpass {
+    pub fn new(r1: f64, r2: f64, r3: f64, c1: f64, c2: f64) -> Self {
+        Self {
+            r1,
+            r2,
+            r3,
+            c1,
+            c2,
+            amp: 1e5,
+            vcc: 12.,
+        }
+    }
+
+    /// Passband gain, negative as the filter inverts.
+    pub fn gain(&self) -> f64 {
+        -self.r2 / self.r1
+    }
+
+    pub fn fc(&self) -> f64 {
+        (TAU * (self.r2 * self.r3 * self.c1 * self.c2).sqrt()).recip()
+    }
+
+    pub fn q(&self) -> f64 {
+        let (r1, r2, r3) = (self.r1, self.r2, self.r3);
+        (r2 * r3 * self.c1 * self.c2).sqrt() / (self.c2 * (r2 + r3 + r2 * r3 / r1))
+    }
+
+    /// Move the cutoff to `fc` by scaling both capacitors, which keeps the gain and Q.
+    pub fn set_fc(&mut self, fc: f64) {
+        let ratio = self.fc() / fc;
+        self.c1 *= ratio;
+        self.c2 *= ratio;
+    }
+
+    /// Output and inverting input voltages, for a voltage `v_c2` across `C2`.
+    fn op_amp<T: Float>(&self, v_c2: T) -> (T, T) {
+        let amp = T::from(self.amp).unwrap();
+        let vcc = T::from(self.vcc).unwrap();
+        let v_out = clamp(-vcc, vcc, amp / (T::one() + amp) * v_c2);
+        (v_out, v_out - v_c2)
+    }
+}
+
+impl Lerp for MfbLowpass {
+    /// Interpolates the capacitors' reciprocals, which sweeps the cutoff linearly.
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            r1: self.r1.lerp(&other.r1, t),
+            r2: self.r2.lerp(&other.r2, t),
+            r3: self.r3.lerp(&other.r3, t),
+            c1: self.c1.recip().lerp(&other.c1.recip(), t).recip(),
+            c2: self.c2.recip().lerp(&other.c2.recip(), t).recip(),
+            amp: self.amp.lerp(&other.amp, t),
+            vcc: self.vcc.lerp(&other.vcc, t),
+        }
+    }
+}
+
+/// The state holds the voltage across `C1`, which is the one of node `A`, and across `C2`, from
+/// the inverting input to the output.
+impl OdeSystem<2> for MfbLowpass {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 2]) -> [T; 2] {
+        let [v_a, v_c2] = *x;
+        let [r1, r2, r3, c1, c2] =
+            [self.r1, self.r2, self.r3, self.c1, self.c2].map(|v| T::from(v).unwrap());
+        let (v_out, v_n) = self.op_amp(v_c2);
+        let i_r3 = (v_a - v_n) / r3;
+        let dv_a = ((u - v_a) / r1 + (v_out - v_a) / r2 - i_r3) / c1;
+        // The current through R3 can only go on through C2
+        let dv_c2 = -i_r3 / c2;
+        [dv_a, dv_c2]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 2]) -> f64 {
+        self.op_amp(x[1]).0
+    }
+}
+
+/// Small-signal model with an ideal op-amp.
+impl LinearCircuit<2> for MfbLowpass {
+    fn state_space(&self) -> StateSpace<2> {
+        let (g1, g2, g3) = (self.r1.recip(), self.r2.recip(), self.r3.recip());
+        StateSpace {
+            a: [
+                [-(g1 + g2 + g3) / self.c1, g2 / self.c1],
+                [-g3 / self.c2, 0.],
+            ],
+            b: [g1 / self.c1, 0.],
+            c: [0., 1.],
+            d: 0.,
+        }
+    }
+}
+
+/// Multiple-feedback bandpass: `R1` from the input to node `A`, which has `R2` to ground, `C1` to
+/// the output and `C2` to the inverting input, with `R3` from the output to the inverting input.
+#[derive(Debug, Clone, Copy)]
+pub struct MfbBandpass {
+    pub r1: f64,
+    pub r2: f64,
+    pub r3: f64,
+    pub c1: f64,
+    pub c2: f64,
+    pub amp: f64,
+    pub vcc: f64,
+}
+
+impl MfbBandpass {
+    pub fn new(r1: f64, r2: f64, r3: f64, c1: f64, c2: f64) -> Self {
+        Self {
+            r1,
+            r2,
+            r3,
+            c1,
+            c2,
+            amp: 1e5,
+            vcc: 12.,
+        }
+    }
+
+    /// Gain at the center frequency, negative as the filter inverts.
+    pub fn gain(&self) -> f64 {
+        -self.r3 * self.c2 / (self.r1 * (self.c1 + self.c2))
+    }
+
+    /// Center frequency.
+    pub fn fc(&self) -> f64 {
+        let g = self.r1.recip() + self.r2.recip();
+        (g / (self.r3 * self.c1 * self.c2)).sqrt() / TAU
+    }
+
+    pub fn q(&self) -> f64 {
+        TAU * self.fc() * self.r3 * self.c1 * self.c2 / (self.c1 + self.c2)
+    }
+
+    /// Move the center frequency to `fc` by scaling both capacitors, which keeps the gain and Q.
+    pub fn set_fc(&mut self, fc: f64) {
+        let ratio = self.fc() / fc;
+        self.c1 *= ratio;
+        self.c2 *= ratio;
+    }
+
+    /// Output and inverting input voltages, for the voltages across `C1` and `C2`.
+    fn op_amp<T: Float>(&self, v_c1: T, v_c2: T) -> (T, T) {
+        let amp = T::from(self.amp).unwrap();
+        let vcc = T::from(self.vcc).unwrap();
+        // v_n = v_c1 + v_out - v_c2
+        let v_out = clamp(-vcc, vcc, amp / (T::one() + amp) * (v_c2 - v_c1));
+        (v_out, v_c1 + v_out - v_c2)
+    }
+}
+
+impl Lerp for MfbBandpass {
+    /// Interpolates the capacitors' reciprocals, which sweeps the center frequency linearly.
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            r1: self.r1.lerp(&other.r1, t),
+            r2: self.r2.lerp(&other.r2, t),
+            r3: self.r3.lerp(&other.r3, t),
+            c1: self.c1.recip().lerp(&other.c1.recip(), t).recip(),
+            c2: self.c2.recip().lerp(&other.c2.recip(), t).recip(),
+            amp: self.amp.lerp(&other.amp, t),
+            vcc: self.vcc.lerp(&other.vcc, t),
+        }
+    }
+}
+
+/// The state holds the voltages across `C1`, from node `A` to the output, and `C2`, from node `A`
+/// to the inverting input.
+impl OdeSystem<2> for MfbBandpass {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 2]) -> [T; 2] {
+        let [v_c1, v_c2] = *x;
+        let [r1, r2, r3, c1, c2] =
+            [self.r1, self.r2, self.r3, self.c1, self.c2].map(|v| T::from(v).unwrap());
+        let (v_out, v_n) = self.op_amp(v_c1, v_c2);
+        let v_a = v_c1 + v_out;
+        // The current through C2 can only go on through R3
+        let i_c2 = (v_n - v_out) / r3;
+        let i_c1 = (u - v_a) / r1 - v_a / r2 - i_c2;
+        [i_c1 / c1, i_c2 / c2]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 2]) -> f64 {
+        self.op_amp(x[0], x[1]).0
+    }
+}
+
+/// Small-signal model with an ideal op-amp.
+impl LinearCircuit<2> for MfbBandpass {
+    fn state_space(&self) -> StateSpace<2> {
+        let (g1, g2, g3) = (self.r1.recip(), self.r2.recip(), self.r3.recip());
+        let (c1, c2) = (self.c1, self.c2);
+        // With the inverting input at ground, v_out = -v_c1 + v_c2 and v_a = v_c2
+        StateSpace {
+            a: [[-g3 / c1, -(g1 + g2 - g3) / c1], [g3 / c2, -g3 / c2]],
+            b: [g1 / c1, 0.],
+            c: [-1., 1.],
+            d: 0.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    use approx::assert_relative_eq;
+
+    use crate::lpf::test_utils::measure_gain;
+    use crate::ode::{OdeSystem, Solver, Trapezoidal};
+    use crate::state_space::LinearCircuit;
+    use crate::utils::Lerp;
+
+    use super::{MfbBandpass, MfbLowpass, MfbState};
+
+    /// Steady-state gain of a sine wave at `freq` Hz through the simulated filter.
+    fn simulated_gain<F>(filter: &F, freq: f64, amplitude: f64) -> f64
+    where
+        F: OdeSystem<2> + Lerp + Copy,
+    {
+        let step = (4. * 44100f64).recip();
+        let mut state = MfbState::new(Solver::Rk4);
+        measure_gain(freq, step, |x| {
+            state.set_v_in(amplitude * x);
+            state.process(filter, step) / amplitude
+        })
+    }
+
+    #[test]
+    fn test_mfb_lowpass() {
+        // Butterworth with a gain of -2
+        let filter = MfbLowpass::new(5e3, 10e3, 10e3, 80e-9, 10e-9);
+        assert_relative_eq!(filter.gain(), -2.);
+        assert_relative_eq!(filter.q(), FRAC_1_SQRT_2, max_relative = 1e-9);
+        let fc = filter.fc();
+        assert_relative_eq!(fc, 562.7, max_relative = 1e-4);
+
+        let sys = filter.state_space();
+        assert_relative_eq!(sys.dc_gain(), -2., max_relative = 1e-9);
+        for freq in [fc / 4., fc, 4. * fc] {
+            let w = freq / fc;
+            let expected = 2. / f64::hypot(1. - w * w, w / filter.q());
+            assert_relative_eq!(
+                sys.frequency_response(freq).norm(),
+                expected,
+                max_relative = 1e-9
+            );
+            assert_relative_eq!(
+                simulated_gain(&filter, freq, 0.1),
+                expected,
+                max_relative = 1e-3
+            );
+        }
+
+        // Moving the cutoff keeps the rest
+        let mut moved = filter;
+        moved.set_fc(3e3);
+        assert_relative_eq!(moved.fc(), 3e3, max_relative = 1e-9);
+        assert_relative_eq!(moved.q(), filter.q(), max_relative = 1e-9);
+        assert_relative_eq!(moved.gain(), filter.gain());
+    }
+
+    #[test]
+    fn test_mfb_bandpass() {
+        let filter = MfbBandpass::new(100e3, 1.1e3, 200e3, 10e-9, 10e-9);
+        let (f0, q) = (filter.fc(), filter.q());
+        assert_relative_eq!(filter.gain(), -1.);
+        assert_relative_eq!(f0, 1078.91, max_relative = 1e-5);
+        assert_relative_eq!(q, 6.779, max_relative = 1e-4);
+
+        let sys = filter.state_space();
+        assert_relative_eq!(sys.frequency_response(f0).norm(), 1., max_relative = 1e-9);
+        assert_relative_eq!(sys.frequency_response(f0).re, -1., max_relative = 1e-9);
+        // Band edges, a bandwidth of f0 / Q apart
+        let half = f0 / (2. * q);
+        let center = f64::hypot(half, f0);
+        let (lo, hi) = (center - half, center + half);
+        assert_relative_eq!(hi - lo, f0 / q, max_relative = 1e-9);
+        for freq in [lo, hi] {
+            assert_relative_eq!(
+                sys.frequency_response(freq).norm(),
+                FRAC_1_SQRT_2,
+                max_relative = 1e-9
+            );
+        }
+        assert_relative_eq!(simulated_gain(&filter, f0, 0.1), 1., max_relative = 1e-3);
+    }
+
+    #[test]
+    fn test_mfb_rails() {
+        let step = (4. * 44100f64).recip();
+        let filter = MfbLowpass::new(1e3, 10e3, 10e3, 40e-9, 10e-9);
+        let mut state = MfbState::new(Solver::Trapezoidal(Trapezoidal::default()));
+        state.set_v_in(-10.);
+        let mut out = 0.;
+        for _ in 0..10000 {
+            out = state.process(&filter, step);
+            assert!(out.abs() <= filter.vcc);
+        }
+        assert_eq!(out, filter.vcc);
+        assert!(!state.take_diverged());
+    }
+}
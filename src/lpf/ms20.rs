@@ -0,0 +1,293 @@
+//! Sallen-Key filters with diode-limited resonance, after the Korg MS-20.
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::{saturate, SallenKey, SallenKeyHighpass};
+
+const NEWTON_ITERATIONS: usize = 16;
+const NEWTON_TOLERANCE: f64 = 1e-12;
+
+/// Pair of antiparallel diodes to ground, fed through a series resistor.
+#[derive(Debug, Clone, Copy)]
+pub struct DiodeClipper {
+    /// Saturation current of the diodes.
+    pub is: f64,
+    /// Thermal voltage of the diodes times their ideality factor.
+    pub n_vt: f64,
+    /// Series resistor.
+    pub r: f64,
+}
+
+impl Default for DiodeClipper {
+    /// 1N4148 diodes through 1 kΩ.
+    fn default() -> Self {
+        Self {
+            is: 2.52e-9,
+            n_vt: 1.752 * 25.85e-3,
+            r: 1e3,
+        }
+    }
+}
+
+impl DiodeClipper {
+    /// Voltage across the diodes when `v` is applied to the resistor, solving the Shockley
+    /// equation `v = v_d + 2 r is sinh(v_d / n_vt)`.
+    pub fn clip<T: Float>(&self, v: T) -> T {
+        let n_vt = T::from(self.n_vt).unwrap();
+        let r_is = T::from(2. * self.r * self.is).unwrap();
+        let tolerance = T::from(NEWTON_TOLERANCE).unwrap();
+        // Both the input voltage and the voltage with the resistor alone in the way bound the
+        // solution from above, from which Newton's method converges monotonically
+        let mut v_d = v.signum() * v.abs().min(n_vt * (v.abs() / r_is).asinh());
+        for _ in 0..NEWTON_ITERATIONS {
+            let (sinh, cosh) = ((v_d / n_vt).sinh(), (v_d / n_vt).cosh());
+            let f = v_d + r_is * sinh - v;
+            v_d = v_d - f / (T::one() + r_is * cosh / n_vt);
+            if f.abs() < tolerance {
+                break;
+            }
+        }
+        v_d
+    }
+}
+
+/// [`SallenKey`] lowpass where the op-amp is a buffer, and the extra `(k - 1) v_c2` of feedback
+/// which sets the resonance goes through a [`DiodeClipper`] before reaching `C1`. This keeps the
+/// resonance from running away into the rails, and makes it scream instead.
+///
+/// The small-signal response is the one of the plain [`SallenKey`].
+#[derive(Debug, Clone, Copy)]
+pub struct Ms20Lowpass {
+    sallen_key: SallenKey,
+    pub drive: f64,
+    pub diodes: DiodeClipper,
+}
+
+impl Ms20Lowpass {
+    pub fn new(fc: f64, q: f64) -> Self {
+        Self {
+            sallen_key: SallenKey::new(fc, q),
+            drive: 1.,
+            diodes: DiodeClipper::default(),
+        }
+    }
+
+    pub fn set_fc(&mut self, fc: f64) {
+        self.sallen_key.set_fc(fc);
+    }
+
+    pub fn set_q(&mut self, q: f64) {
+        self.sallen_key.set_q(q);
+    }
+
+    /// Set the gain applied to the input signal before the filter.
+    pub fn set_drive(&mut self, drive: f64) {
+        self.drive = drive;
+    }
+
+    fn v_out<T: Float>(&self, v_c2: T) -> T {
+        let excess = T::from(self.sallen_key.k - 1.).unwrap() * v_c2;
+        saturate(self.sallen_key.vcc, v_c2 + self.diodes.clip(excess))
+    }
+}
+
+impl Lerp for Ms20Lowpass {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            sallen_key: self.sallen_key.lerp(&other.sallen_key, t),
+            drive: self.drive.lerp(&other.drive, t),
+            diodes: other.diodes,
+        }
+    }
+}
+
+/// The state holds the voltages across `C1` and `C2`, as in [`SallenKey`].
+impl OdeSystem<2> for Ms20Lowpass {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 2]) -> [T; 2] {
+        let [v_c1, v_c2] = *x;
+        let u = T::from(self.drive).unwrap() * u;
+        let rc = T::from(self.sallen_key.rc).unwrap();
+        let two = T::from(2).unwrap();
+        let v_a = v_c1 + self.v_out(v_c2);
+        let dv_c1 = (u - two * v_a + v_c2) / rc;
+        let dv_c2 = (v_a - v_c2) / rc;
+        [dv_c1, dv_c2]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 2]) -> f64 {
+        self.v_out(x[1])
+    }
+}
+
+/// Small-signal model, where the diodes don't conduct.
+impl LinearCircuit<2> for Ms20Lowpass {
+    fn state_space(&self) -> StateSpace<2> {
+        let mut sys = self.sallen_key.state_space();
+        sys.b = sys.b.map(|b| self.drive * b);
+        sys
+    }
+}
+
+/// [`SallenKeyHighpass`] with the same diode-limited resonance as [`Ms20Lowpass`].
+#[derive(Debug, Clone, Copy)]
+pub struct Ms20Highpass {
+    sallen_key: SallenKeyHighpass,
+    pub drive: f64,
+    pub diodes: DiodeClipper,
+}
+
+impl Ms20Highpass {
+    pub fn new(fc: f64, q: f64) -> Self {
+        Self {
+            sallen_key: SallenKeyHighpass::new(fc, q),
+            drive: 1.,
+            diodes: DiodeClipper::default(),
+        }
+    }
+
+    pub fn set_fc(&mut self, fc: f64) {
+        self.sallen_key.set_fc(fc);
+    }
+
+    pub fn set_q(&mut self, q: f64) {
+        self.sallen_key.set_q(q);
+    }
+
+    /// Set the gain applied to the input signal before the filter.
+    pub fn set_drive(&mut self, drive: f64) {
+        self.drive = drive;
+    }
+
+    fn v_out<T: Float>(&self, v_b: T) -> T {
+        let excess = T::from(self.sallen_key.k - 1.).unwrap() * v_b;
+        saturate(self.sallen_key.vcc, v_b + self.diodes.clip(excess))
+    }
+}
+
+impl Lerp for Ms20Highpass {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            sallen_key: self.sallen_key.lerp(&other.sallen_key, t),
+            drive: self.drive.lerp(&other.drive, t),
+            diodes: other.diodes,
+        }
+    }
+}
+
+/// The state holds the voltages across `C1` and `C2`, as in [`SallenKeyHighpass`].
+impl OdeSystem<2> for Ms20Highpass {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 2]) -> [T; 2] {
+        let [v_c1, v_c2] = *x;
+        let u = T::from(self.drive).unwrap() * u;
+        let rc = T::from(self.sallen_key.rc).unwrap();
+        let v_a = u - v_c1;
+        let v_b = v_a - v_c2;
+        let dv_c1 = (v_b + v_a - self.v_out(v_b)) / rc;
+        let dv_c2 = v_b / rc;
+        [dv_c1, dv_c2]
+    }
+
+    fn output(&self, u: f64, x: &[f64; 2]) -> f64 {
+        self.v_out(self.drive * u - x[0] - x[1])
+    }
+}
+
+/// Small-signal model, where the diodes don't conduct.
+impl LinearCircuit<2> for Ms20Highpass {
+    fn state_space(&self) -> StateSpace<2> {
+        let mut sys = self.sallen_key.state_space();
+        sys.b = sys.b.map(|b| self.drive * b);
+        sys.d *= self.drive;
+        sys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::dual::Dual;
+    use crate::lpf::{SallenKey, SallenKeyHighpass, SallenKeyState};
+    use crate::ode::{Solver, Trapezoidal};
+    use crate::state_space::LinearCircuit;
+
+    use super::{DiodeClipper, Ms20Highpass, Ms20Lowpass};
+
+    #[test]
+    fn test_diode_clipper() {
+        let diodes = DiodeClipper::default();
+        for v in [-10., -1., -0.1, 0., 1e-3, 0.5, 2., 100.] {
+            let v_d = diodes.clip(v);
+            let residual = v_d + 2. * diodes.r * diodes.is * (v_d / diodes.n_vt).sinh() - v;
+            assert!(residual.abs() < 1e-9, "residual {residual} at {v} V");
+            assert!(v_d.abs() <= f64::abs(v) && v_d.abs() < 1.);
+
+            // Implicit derivative of the Shockley equation
+            let dv = Dual::<1>::variable(v, 0);
+            let cosh = (v_d / diodes.n_vt).cosh();
+            let expected = (1. + 2. * diodes.r * diodes.is * cosh / diodes.n_vt).recip();
+            assert_relative_eq!(diodes.clip(dv).eps[0], expected, max_relative = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_ms20_small_signal() {
+        let (fc, q) = (1e3, 2.);
+        let lowpass = (SallenKey::new(fc, q), Ms20Lowpass::new(fc, q));
+        let highpass = (SallenKeyHighpass::new(fc, q), Ms20Highpass::new(fc, q));
+        for freq in [fc / 4., fc, 4. * fc] {
+            assert_relative_eq!(
+                lowpass.1.state_space().frequency_response(freq).norm(),
+                lowpass.0.state_space().frequency_response(freq).norm()
+            );
+            assert_relative_eq!(
+                highpass.1.state_space().frequency_response(freq).norm(),
+                highpass.0.state_space().frequency_response(freq).norm()
+            );
+        }
+
+        // Below the knee of the diodes, the simulation follows the plain Sallen-Key
+        let step = (4. * 44100f64).recip();
+        let filters = (SallenKey::new(fc, q), Ms20Lowpass::new(fc, q));
+        let mut states = (
+            SallenKeyState::new(Solver::Rk4),
+            SallenKeyState::new(Solver::Rk4),
+        );
+        states.0.set_v_in(1e-3);
+        states.1.set_v_in(1e-3);
+        for _ in 0..1000 {
+            let expected = states.0.process(&filters.0, step);
+            let out = states.1.process(&filters.1, step);
+            assert_relative_eq!(out, expected, max_relative = 1e-2, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_ms20_diode_limited_resonance() {
+        let step = (4. * 44100f64).recip();
+        let (fc, q) = (1e3, 20.);
+        // The op-amp rails stop the plain Sallen-Key, well before the diodes stop the MS-20
+        let sallen_key = SallenKey::new(fc, q);
+        for drive in [1., 4.] {
+            let mut filter = Ms20Lowpass::new(fc, q);
+            filter.set_drive(drive);
+            let solver = Solver::Trapezoidal(Trapezoidal::default());
+            let mut states = (SallenKeyState::new(solver), SallenKeyState::new(solver));
+            let (mut max_sallen_key, mut max_ms20) = (0f64, 0f64);
+            for n in 0..10000 {
+                let v_in = if (n / 100) % 2 == 0 { 1. } else { -1. };
+                states.0.set_v_in(drive * v_in);
+                states.1.set_v_in(v_in);
+                max_sallen_key = max_sallen_key.max(states.0.process(&sallen_key, step).abs());
+                max_ms20 = max_ms20.max(states.1.process(&filter, step).abs());
+            }
+            assert_eq!(max_sallen_key, sallen_key.vcc);
+            assert!(max_ms20 < 0.5 * sallen_key.vcc);
+            assert!(!states.1.take_diverged());
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Cascade of OTA lowpass stages, as in the CEM3320 and SSM2040.
+
+use std::f64::consts::TAU;
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::CircuitState;
+
+/// Four OTA integrators wired as followers, each charging a capacitor with a current which is
+/// the `tanh` of the difference between its input and its output. The output is tapped after the
+/// selected number of poles, and fed back to the input with gain `resonance`. The stages past the
+/// output keep running, so that changing the number of poles doesn't bring in stale state.
+///
+/// The cutoff follows the exponential converter driving the OTA bias currents: it doubles with
+/// every volt of control voltage, starting from `base_fc` at 0 V.
+#[derive(Debug, Clone, Copy)]
+pub struct OtaCascade {
+    cv: f64,
+    poles: usize,
+    resonance: f64,
+    pub base_fc: f64,
+}
+
+impl OtaCascade {
+    /// Middle C, at 0 V.
+    pub const BASE_FC: f64 = 261.625_565_300_598_6;
+
+    pub fn new(cv: f64, poles: usize) -> Self {
+        Self {
+            cv,
+            poles: poles.clamp(1, 4),
+            resonance: 0.,
+            base_fc: Self::BASE_FC,
+        }
+    }
+
+    /// Set the control voltage, in volts per octave.
+    pub fn set_cv(&mut self, cv: f64) {
+        self.cv = cv;
+    }
+
+    /// Control voltage giving a cutoff of `fc` Hz.
+    pub fn cv_for(&self, fc: f64) -> f64 {
+        (fc / self.base_fc).log2()
+    }
+
+    pub fn fc(&self) -> f64 {
+        self.base_fc * self.cv.exp2()
+    }
+
+    /// Set the number of poles, between 1 and 4.
+    pub fn set_poles(&mut self, poles: usize) {
+        self.poles = poles.clamp(1, 4);
+    }
+
+    pub fn set_resonance(&mut self, resonance: f64) {
+        self.resonance = resonance.max(0.);
+    }
+
+    /// Feedback gain at which the filter self-oscillates, when the stages can reach -180° of phase
+    /// shift at a finite frequency, which takes at least 3 poles: with 3, each shifts by 60° at
+    /// `√3 fc`, where it has a gain of 1/2, and with 4, each shifts by 45° at `fc`, with a gain of
+    /// `1/√2`.
+    pub fn self_oscillation(&self) -> Option<f64> {
+        match self.poles {
+            3 => Some(8.),
+            4 => Some(4.),
+            _ => None,
+        }
+    }
+
+    fn wc<T: Float>(&self) -> T {
+        T::from(TAU * self.base_fc).unwrap() * T::from(self.cv).unwrap().exp2()
+    }
+}
+
+impl Lerp for OtaCascade {
+    /// Interpolates the control voltage, which sweeps the cutoff exponentially.
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            cv: self.cv.lerp(&other.cv, t),
+            poles: other.poles,
+            resonance: self.resonance.lerp(&other.resonance, t),
+            base_fc: self.base_fc.lerp(&other.base_fc, t),
+        }
+    }
+}
+
+/// The state holds the capacitor voltage of each stage.
+impl OdeSystem<4> for OtaCascade {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 4]) -> [T; 4] {
+        let wc = self.wc::<T>();
+        let mut v_in = u - T::from(self.resonance).unwrap() * x[self.poles - 1];
+        let mut dx = [T::zero(); 4];
+        for i in 0..4 {
+            dx[i] = wc * (v_in - x[i]).tanh();
+            v_in = x[i];
+        }
+        dx
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 4]) -> f64 {
+        x[self.poles - 1]
+    }
+}
+
+/// Small-signal model, where no OTA saturates.
+impl LinearCircuit<4> for OtaCascade {
+    fn state_space(&self) -> StateSpace<4> {
+        let wc = self.wc::<f64>();
+        let mut a = [[0.; 4]; 4];
+        for i in 0..4 {
+            a[i][i] = -wc;
+            if i > 0 {
+                a[i][i - 1] = wc;
+            }
+        }
+        a[0][self.poles - 1] -= wc * self.resonance;
+        let mut c = [0.; 4];
+        c[self.poles - 1] = 1.;
+        StateSpace {
+            a,
+            b: [wc, 0., 0., 0.],
+            c,
+            d: 0.,
+        }
+    }
+}
+
+/// State of the cascade, holding the capacitor voltage of each stage.
+pub type OtaCascadeState = CircuitState<OtaCascade, 4>;
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    use approx::assert_relative_eq;
+
+    use crate::lpf::test_utils::{measure_gain, ring};
+    use crate::ode::{Solver, Trapezoidal};
+    use crate::state_space::LinearCircuit;
+
+    use super::{OtaCascade, OtaCascadeState};
+
+    #[test]
+    fn test_ota_volt_per_octave() {
+        let step = (4. * 44100f64).recip();
+        let mut filter = OtaCascade::new(0., 1);
+        for cv in [-2., 0., 1., 3.] {
+            filter.set_cv(cv);
+            let fc = OtaCascade::BASE_FC * f64::exp2(cv);
+            assert_relative_eq!(filter.fc(), fc);
+            assert_relative_eq!(filter.cv_for(fc), cv, epsilon = 1e-12);
+
+            // Small sine wave at the cutoff, where a single pole is 3 dB down
+            let amplitude = 1e-3;
+            let mut state = OtaCascadeState::new(Solver::Rk4);
+            let gain = measure_gain(fc, step, |x| {
+                state.set_v_in(amplitude * x);
+                state.process(&filter, step) / amplitude
+            });
+            assert_relative_eq!(gain, FRAC_1_SQRT_2, max_relative = 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_ota_poles() {
+        let fc = OtaCascade::BASE_FC;
+        for poles in 1..=4 {
+            let filter = OtaCascade::new(0., poles);
+            let sys = filter.state_space();
+            for freq in [fc / 2., fc, 10. * fc] {
+                let w = freq / fc;
+                let expected = f64::powf(1. + w * w, -0.5 * poles as f64);
+                assert_relative_eq!(
+                    sys.frequency_response(freq).norm(),
+                    expected,
+                    max_relative = 1e-9
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_ota_self_oscillation() {
+        let step = (4. * 44100f64).recip();
+        // After a short kick, the ringing dies out below the threshold and builds up above it
+        let kick = |poles: usize, resonance: f64| {
+            let mut filter = OtaCascade::new(2., poles);
+            filter.set_resonance(resonance);
+            let mut state = OtaCascadeState::new(Solver::Trapezoidal(Trapezoidal::default()));
+            let ring = ring(step, 100. / filter.fc(), step, 2, |x| {
+                state.set_v_in(x);
+                state.process(&filter, step)
+            });
+            ring.last / ring.first
+        };
+
+        for poles in [3, 4] {
+            let k = OtaCascade::new(0., poles).self_oscillation().unwrap();
+            assert!(kick(poles, 0.95 * k) < 0.1);
+            assert!(kick(poles, 1.05 * k) > 10.);
+        }
+        // Two poles never get there
+        assert!(OtaCascade::new(0., 2).self_oscillation().is_none());
+        assert!(kick(2, 100.) < 0.1);
+    }
+}
@@ -0,0 +1,532 @@
+//! All-pass phaser, with stages swept by a JFET or an OTA.
+
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::{CircuitState, RcFilter};
+
+/// First-order all-pass stage: an [`RcFilter`] feeding the non-inverting input of an op-amp with
+/// equal input and feedback resistors, so that the output is twice the capacitor voltage minus
+/// the input. Its response is `(1 - sRC) / (1 + sRC)`, of unity gain with a phase going from 0 to
+/// -180°, through -90° at the cutoff of the RC stage.
+#[derive(Debug, Clone, Copy)]
+pub struct AllPass {
+    pub rc: RcFilter,
+}
+
+impl AllPass {
+    pub fn new(fc: f64) -> Self {
+        Self {
+            rc: RcFilter::new(fc),
+        }
+    }
+
+    pub fn set_fc(&mut self, fc: f64) {
+        self.rc.set_fc(fc);
+    }
+}
+
+impl Lerp for AllPass {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            rc: self.rc.lerp(&other.rc, t),
+        }
+    }
+}
+
+/// The state holds the voltage across the capacitor of the RC stage.
+impl OdeSystem<1> for AllPass {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 1]) -> [T; 1] {
+        self.rc.derivative(u, x)
+    }
+
+    fn output(&self, u: f64, x: &[f64; 1]) -> f64 {
+        2. * x[0] - u
+    }
+}
+
+impl LinearCircuit<1> for AllPass {
+    fn state_space(&self) -> StateSpace<1> {
+        let rc = self.rc.state_space();
+        StateSpace {
+            c: [2.],
+            d: -1.,
+            ..rc
+        }
+    }
+}
+
+/// N-channel JFET used as a voltage-controlled resistor, below saturation. The channel conducts
+/// `β (2 V_ov V_ds - V_ds²)`, where the overdrive `V_ov` is how far the gate voltage is above the
+/// pinch-off voltage `v_p`, so that the channel has a resistance of `r_on` at `V_gs = 0` for small
+/// signals, which goes up hyperbolically as the gate goes towards pinch-off. Larger signals see
+/// a higher resistance, up to the saturation current at `V_ds = V_ov`.
+///
+/// Instead of switching off at pinch-off, the overdrive is smoothed over `v_s` volts so that the
+/// channel fades out exponentially, as the subthreshold conduction of a real device does.
+#[derive(Debug, Clone, Copy)]
+pub struct Jfet {
+    pub v_p: f64,
+    pub r_on: f64,
+    pub v_s: f64,
+}
+
+impl Default for Jfet {
+    fn default() -> Self {
+        Self {
+            v_p: -2.,
+            r_on: 400.,
+            v_s: 0.1,
+        }
+    }
+}
+
+impl Jfet {
+    fn beta(&self) -> f64 {
+        (2. * self.r_on * self.v_p.abs()).recip()
+    }
+
+    /// Effective overdrive at the gate voltage `v_gs`.
+    pub fn overdrive(&self, v_gs: f64) -> f64 {
+        self.v_s * ((v_gs - self.v_p) / self.v_s).exp().ln_1p()
+    }
+
+    /// Gate voltage giving an effective overdrive of `v_ov`.
+    pub fn gate_voltage(&self, v_ov: f64) -> f64 {
+        self.v_p + self.v_s * (v_ov / self.v_s).exp_m1().ln()
+    }
+
+    /// Small-signal channel conductance for an overdrive of `v_ov`.
+    pub fn conductance(&self, v_ov: f64) -> f64 {
+        2. * self.beta() * v_ov
+    }
+
+    /// Channel current for an overdrive of `v_ov` and a drain-source voltage of `v_ds`, which is
+    /// symmetric as the drain and source swap roles when `v_ds` changes sign.
+    fn current<T: Float>(&self, v_ov: f64, v_ds: T) -> T {
+        let v_ov = T::from(v_ov).unwrap();
+        let v = v_ds.abs().min(v_ov);
+        T::from(self.beta()).unwrap() * v_ds.signum() * (T::from(2.).unwrap() * v_ov * v - v * v)
+    }
+}
+
+/// Device setting the resistance of the phaser stages.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PhaserElement {
+    /// [`Jfet`] in place of the resistor, with a fixed resistor across it keeping the cutoff up
+    /// when it pinches off. The cutoff goes up linearly with the gate voltage, except close to
+    /// pinch-off where it fades out towards that of the fixed resistor.
+    #[default]
+    Jfet,
+    /// OTA follower charging the capacitor with a current which is the `tanh` of the difference
+    /// between the input and the capacitor voltage, with its bias current from an exponential
+    /// converter. The cutoff sweeps the same range as with the JFET, exponentially.
+    Ota,
+}
+
+/// Phaser made of up to [`Self::MAX_STAGES`] [`AllPass`] stages in series, the resistor of each
+/// being replaced by a [`PhaserElement`]. The output of the last stage is fed back to the input
+/// with gain `feedback`, and mixed in equal parts with the signal entering the first stage. The
+/// phase shifts of the stages then cancel the mix out at a series of notches, while the feedback
+/// brings up the frequencies in between.
+///
+/// The position of the elements goes from 0, at the lowest cutoff, to 1, at the highest one. It
+/// is set by `center` and swept by an internal triangle LFO at `rate` Hz with an amplitude of
+/// `depth`, which [`PhaserLfo`] runs.
+///
+/// The state always holds [`Self::MAX_STAGES`] stages, of which the ones past the set number
+/// of stages stay at rest.
+#[derive(Debug, Clone, Copy)]
+pub struct Phaser {
+    pub element: PhaserElement,
+    pub jfet: Jfet,
+    /// Resistor across the JFET.
+    pub r: f64,
+    pub c: f64,
+    center: f64,
+    depth: f64,
+    rate: f64,
+    feedback: f64,
+    stages: usize,
+    /// Value of the LFO, between -1 and 1.
+    lfo: f64,
+}
+
+impl Default for Phaser {
+    fn default() -> Self {
+        Self::new(PhaserElement::default())
+    }
+}
+
+impl Phaser {
+    pub const MAX_STAGES: usize = 12;
+
+    /// Four stages swept by `element`, without feedback, centered in the middle of their range.
+    pub fn new(element: PhaserElement) -> Self {
+        Self {
+            element,
+            jfet: Jfet::default(),
+            r: 150e3,
+            c: 47e-9,
+            center: 0.5,
+            depth: 0.,
+            rate: 0.5,
+            feedback: 0.,
+            stages: 4,
+            lfo: 0.,
+        }
+    }
+
+    /// Set the number of stages, between 1 and [`Self::MAX_STAGES`].
+    pub fn set_stages(&mut self, stages: usize) {
+        self.stages = stages.clamp(1, Self::MAX_STAGES);
+    }
+
+    pub fn stages(&self) -> usize {
+        self.stages
+    }
+
+    /// Set the feedback gain, kept within `(-1, 1)` where the loop stays stable.
+    pub fn set_feedback(&mut self, feedback: f64) {
+        self.feedback = feedback.clamp(-0.99, 0.99);
+    }
+
+    /// Set the position of the elements around which the LFO sweeps, between 0 and 1.
+    pub fn set_center(&mut self, center: f64) {
+        self.center = center.clamp(0., 1.);
+    }
+
+    /// Set the center position so that the stages have a cutoff of `fc` Hz there.
+    pub fn set_fc(&mut self, fc: f64) {
+        self.set_center(self.position_for(fc));
+    }
+
+    /// Set the amplitude of the LFO sweep, in units of position.
+    pub fn set_depth(&mut self, depth: f64) {
+        self.depth = depth.max(0.);
+    }
+
+    /// Set the LFO rate, in Hz.
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate.max(0.);
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Set the value of the LFO, between -1 and 1.
+    pub fn set_lfo(&mut self, lfo: f64) {
+        self.lfo = lfo.clamp(-1., 1.);
+    }
+
+    /// Position of the elements, once swept by the LFO.
+    pub fn position(&self) -> f64 {
+        (self.center + self.depth * self.lfo).clamp(0., 1.)
+    }
+
+    /// Gate voltage of the JFETs at `position`, from well past pinch-off at 0, where only the
+    /// fixed resistor is left, to 0 V at 1.
+    fn gate_voltage(&self, position: f64) -> f64 {
+        1.5 * self.jfet.v_p * (1. - position)
+    }
+
+    /// Small-signal cutoff of the JFET stages at `position`.
+    fn jfet_fc(&self, position: f64) -> f64 {
+        let v_ov = self.jfet.overdrive(self.gate_voltage(position));
+        (self.jfet.conductance(v_ov) + self.r.recip()) / (TAU * self.c)
+    }
+
+    /// Small-signal cutoff of the stages at `position`.
+    pub fn fc_at(&self, position: f64) -> f64 {
+        match self.element {
+            PhaserElement::Jfet => self.jfet_fc(position),
+            PhaserElement::Ota => {
+                let (fc_min, fc_max) = (self.jfet_fc(0.), self.jfet_fc(1.));
+                fc_min * (fc_max / fc_min).powf(position)
+            }
+        }
+    }
+
+    /// Small-signal cutoff of the stages at the current position.
+    pub fn fc(&self) -> f64 {
+        self.fc_at(self.position())
+    }
+
+    /// Position at which the stages have a cutoff of `fc` Hz, clamped to the range of the
+    /// elements.
+    pub fn position_for(&self, fc: f64) -> f64 {
+        let (fc_min, fc_max) = (self.jfet_fc(0.), self.jfet_fc(1.));
+        let fc = fc.clamp(fc_min, fc_max);
+        let position = match self.element {
+            PhaserElement::Jfet => {
+                let g = TAU * self.c * fc - self.r.recip();
+                let v_ov = g / self.jfet.conductance(1.);
+                1. - self.jfet.gate_voltage(v_ov) / (1.5 * self.jfet.v_p)
+            }
+            PhaserElement::Ota => (fc / fc_min).ln() / (fc_max / fc_min).ln(),
+        };
+        position.clamp(0., 1.)
+    }
+
+    /// Frequencies at which the mix cancels out: each stage shifts the phase by
+    /// `-2 atan(f / fc)`, and the notches fall where the stages add up to an odd multiple of
+    /// -180°.
+    pub fn notches(&self) -> impl Iterator<Item = f64> {
+        let (fc, stages) = (self.fc(), self.stages);
+        (0..stages / 2).map(move |k| fc * ((2 * k + 1) as f64 * FRAC_PI_2 / stages as f64).tan())
+    }
+
+    /// Rate of change of the capacitor voltage of a stage, with `v` across its element.
+    fn stage<T: Float>(&self, v: T) -> T {
+        let position = self.position();
+        match self.element {
+            PhaserElement::Jfet => {
+                let v_ov = self.jfet.overdrive(self.gate_voltage(position));
+                let i = self.jfet.current(v_ov, v) + v / T::from(self.r).unwrap();
+                i / T::from(self.c).unwrap()
+            }
+            PhaserElement::Ota => T::from(TAU * self.fc_at(position)).unwrap() * v.tanh(),
+        }
+    }
+
+    /// Input of each stage, the first one being the sum of the input and the feedback. As each
+    /// stage outputs twice its capacitor voltage minus its input, the output of the last stage,
+    /// returned as the last element, is the input of the first one with alternating signs plus a
+    /// weighted sum of the capacitor voltages.
+    fn stage_inputs<T: Float>(
+        &self,
+        u: T,
+        x: &[T; Phaser::MAX_STAGES],
+    ) -> [T; Phaser::MAX_STAGES + 1] {
+        let two = T::from(2.).unwrap();
+        let (mut sign, mut sum) = (T::one(), T::zero());
+        for i in 0..self.stages {
+            sign = -sign;
+            sum = two * x[i] - sum;
+        }
+        let feedback = T::from(self.feedback).unwrap();
+        let mut w = [T::zero(); Phaser::MAX_STAGES + 1];
+        w[0] = (u + feedback * sum) / (T::one() - feedback * sign);
+        for i in 0..self.stages {
+            w[i + 1] = two * x[i] - w[i];
+        }
+        w[Phaser::MAX_STAGES] = w[self.stages];
+        w
+    }
+}
+
+impl Lerp for Phaser {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            center: self.center.lerp(&other.center, t),
+            depth: self.depth.lerp(&other.depth, t),
+            feedback: self.feedback.lerp(&other.feedback, t),
+            lfo: self.lfo.lerp(&other.lfo, t),
+            ..*other
+        }
+    }
+}
+
+/// The state holds the capacitor voltage of each stage.
+impl OdeSystem<{ Phaser::MAX_STAGES }> for Phaser {
+    fn derivative<T: Float>(&self, u: T, x: &[T; Phaser::MAX_STAGES]) -> [T; Phaser::MAX_STAGES] {
+        let w = self.stage_inputs(u, x);
+        let mut dx = [T::zero(); Phaser::MAX_STAGES];
+        for i in 0..Phaser::MAX_STAGES {
+            // Unused stages decay, which keeps them out of the way of the solvers
+            let v_in = if i < self.stages { w[i] } else { T::zero() };
+            dx[i] = self.stage(v_in - x[i]);
+        }
+        dx
+    }
+
+    fn output(&self, u: f64, x: &[f64; Phaser::MAX_STAGES]) -> f64 {
+        let w = self.stage_inputs(u, x);
+        0.5 * (w[0] + w[Phaser::MAX_STAGES])
+    }
+}
+
+/// Small-signal model, where the elements are linear. As the stage inputs are linear in the input
+/// and the capacitor voltages, their coefficients are read off by probing one at a time.
+impl LinearCircuit<{ Phaser::MAX_STAGES }> for Phaser {
+    fn state_space(&self) -> StateSpace<{ Phaser::MAX_STAGES }> {
+        const N: usize = Phaser::MAX_STAGES;
+        let wc = TAU * self.fc();
+        let probe = |u: f64, j: Option<usize>| {
+            let mut x = [0.; N];
+            if let Some(j) = j {
+                x[j] = 1.;
+            }
+            self.stage_inputs(u, &x)
+        };
+        let mut a = [[0.; N]; N];
+        let mut b = [0.; N];
+        let mut c = [0.; N];
+        let w = probe(1., None);
+        for i in 0..N {
+            if i < self.stages {
+                b[i] = wc * w[i];
+            }
+        }
+        let d = 0.5 * (w[0] + w[N]);
+        for j in 0..N {
+            let w = probe(0., Some(j));
+            for i in 0..N {
+                if i < self.stages {
+                    a[i][j] = wc * w[i];
+                }
+            }
+            a[j][j] -= wc;
+            c[j] = 0.5 * (w[0] + w[N]);
+        }
+        StateSpace { a, b, c, d }
+    }
+}
+
+/// State of the phaser, holding the capacitor voltage of each stage.
+pub type PhaserState = CircuitState<Phaser, { Phaser::MAX_STAGES }>;
+
+/// Triangle LFO sweeping a [`Phaser`] through [`Phaser::set_lfo`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PhaserLfo {
+    /// Phase, in cycles.
+    phase: f64,
+}
+
+impl PhaserLfo {
+    /// Value of the LFO, going from -1 at the start of its cycle to 1 halfway through.
+    pub fn value(&self) -> f64 {
+        1. - 4. * (self.phase - 0.5).abs()
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = 0.;
+    }
+
+    /// Advance the LFO by `step` seconds at `rate` Hz, and return its new value.
+    pub fn process(&mut self, rate: f64, step: f64) -> f64 {
+        self.phase = (self.phase + rate * step).fract();
+        self.value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, TAU};
+
+    use approx::assert_relative_eq;
+
+    use crate::lpf::test_utils::measure_gain;
+    use crate::ode::{Solver, Trapezoidal};
+    use crate::state_space::LinearCircuit;
+
+    use super::{AllPass, Jfet, Phaser, PhaserElement, PhaserLfo, PhaserState};
+
+    #[test]
+    fn test_all_pass() {
+        let fc = 1e3;
+        let sys = AllPass::new(fc).state_space();
+        for freq in [fc / 10., fc, 10. * fc] {
+            assert_relative_eq!(sys.frequency_response(freq).norm(), 1., max_relative = 1e-9);
+        }
+        assert_relative_eq!(sys.dc_gain(), 1., max_relative = 1e-9);
+        assert_relative_eq!(
+            sys.frequency_response(fc).arg(),
+            -FRAC_PI_2,
+            max_relative = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_jfet_sweep() {
+        let jfet = Jfet::default();
+        // Fully on at 0 V, and faded out past pinch-off
+        assert_relative_eq!(
+            jfet.conductance(jfet.overdrive(0.)).recip(),
+            jfet.r_on,
+            max_relative = 1e-6
+        );
+        assert!(jfet.conductance(jfet.overdrive(jfet.v_p - 1.)) < 1e-5 / jfet.r_on);
+        // Linear in the gate voltage well above pinch-off, exponential below
+        let g = |v_gs: f64| jfet.conductance(jfet.overdrive(v_gs));
+        assert_relative_eq!(g(-0.5) - g(-0.8), g(-0.8) - g(-1.1), max_relative = 1e-3);
+        let (low, lower) = (
+            g(jfet.v_p - 1.) / g(jfet.v_p - 1.1),
+            g(jfet.v_p - 1.1) / g(jfet.v_p - 1.2),
+        );
+        assert_relative_eq!(low, lower, max_relative = 1e-3);
+
+        for element in [PhaserElement::Jfet, PhaserElement::Ota] {
+            let mut filter = Phaser::new(element);
+            for fc in [100., 1e3, 5e3] {
+                filter.set_fc(fc);
+                assert_relative_eq!(filter.fc(), fc, max_relative = 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_phaser_notches() {
+        let mut filter = Phaser::default();
+        filter.set_fc(1e3);
+        filter.set_feedback(0.5);
+        for stages in [2, 4, 6] {
+            filter.set_stages(stages);
+            let sys = filter.state_space();
+            for notch in filter.notches() {
+                assert!(sys.frequency_response(notch).norm() < 1e-9);
+            }
+            // Halfway between the notches, the stages are back in phase with the input, where the
+            // feedback lifts the gain to `1 / (1 - feedback)`
+            assert_relative_eq!(sys.dc_gain(), 2., max_relative = 1e-9);
+            if stages == 4 {
+                assert_relative_eq!(sys.frequency_response(1e3).norm(), 2., max_relative = 1e-9);
+            }
+        }
+
+        // The simulation follows the small-signal model for small signals
+        let step = (4. * 44100f64).recip();
+        filter.set_stages(4);
+        let notch = filter.notches().next().unwrap();
+        let [peak, notch] = [1e3, notch].map(|freq| {
+            let mut state = PhaserState::new(Solver::Trapezoidal(Trapezoidal::default()));
+            let gain = measure_gain(freq, step, |x| {
+                state.set_v_in(1e-3 * x);
+                state.process(&filter, step) / 1e-3
+            });
+            assert!(!state.take_diverged());
+            gain
+        });
+        assert_relative_eq!(peak, 2., max_relative = 1e-2);
+        assert!(notch < 5e-2);
+    }
+
+    #[test]
+    fn test_phaser_lfo() {
+        let step = (4. * 44100f64).recip();
+        let mut filter = Phaser::default();
+        filter.set_rate(10.);
+        filter.set_depth(0.5);
+        filter.set_feedback(0.7);
+        let mut state = PhaserState::new(Solver::Trapezoidal(Trapezoidal::default()));
+        let mut lfo = PhaserLfo::default();
+        // The LFO sweeps the whole range over a cycle
+        let (mut low, mut high) = (f64::INFINITY, 0f64);
+        for n in 0..(0.1 / step) as usize {
+            filter.set_lfo(lfo.process(filter.rate(), step));
+            state.set_v_in(f64::sin(TAU * 440. * n as f64 * step));
+            assert!(state.process(&filter, step).abs() < 10.);
+            low = low.min(filter.position());
+            high = high.max(filter.position());
+        }
+        assert_relative_eq!(low, 0., epsilon = 1e-3);
+        assert_relative_eq!(high, 1., epsilon = 1e-3);
+        assert!(!state.take_diverged());
+    }
+}
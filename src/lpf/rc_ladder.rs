@@ -0,0 +1,175 @@
+//! Passive RC ladder, where every stage loads the previous one.
+
+use std::f64::consts::TAU;
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::CircuitState;
+
+/// Cascade of identical passive RC stages with nothing buffering them, so that the current
+/// through each resistor depends on the capacitor voltages on both of its ends. Unlike a cascade
+/// of unloaded [`super::RcFilter`]s, the poles spread apart and the response rolls off more
+/// gently before reaching its asymptotic slope.
+///
+/// With `τ = RC`, the response of `n` stages is `1 / Σ C(n + k, 2k) (sτ)^k` for `k` from 0 to
+/// `n`.
+///
+/// The state always holds [`Self::MAX_ORDER`] stages, of which the ones past the order set at
+/// construction stay at rest.
+#[derive(Debug, Clone, Copy)]
+pub struct RcLadder {
+    rc: f64,
+    order: usize,
+}
+
+impl RcLadder {
+    pub const MAX_ORDER: usize = 8;
+
+    /// Ladder of `order` stages, between 1 and [`Self::MAX_ORDER`], each with a cutoff of `fc` Hz
+    /// on its own.
+    pub fn new(fc: f64, order: usize) -> Self {
+        Self {
+            rc: (TAU * fc).recip(),
+            order: order.clamp(1, Self::MAX_ORDER),
+        }
+    }
+
+    pub fn set_fc(&mut self, fc: f64) {
+        self.rc = (TAU * fc).recip();
+    }
+
+    pub fn fc(&self) -> f64 {
+        (TAU * self.rc).recip()
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+}
+
+impl Lerp for RcLadder {
+    /// Interpolates the cutoff frequency rather than the time constant.
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            rc: self.rc.recip().lerp(&other.rc.recip(), t).recip(),
+            order: other.order,
+        }
+    }
+}
+
+/// The state holds the voltage across each capacitor, from the input side.
+impl OdeSystem<{ RcLadder::MAX_ORDER }> for RcLadder {
+    fn derivative<T: Float>(&self, u: T, x: &[T; RcLadder::MAX_ORDER]) -> [T; RcLadder::MAX_ORDER] {
+        let rc = T::from(self.rc).unwrap();
+        let mut dx = [T::zero(); RcLadder::MAX_ORDER];
+        for i in 0..RcLadder::MAX_ORDER {
+            dx[i] = if i < self.order {
+                let prev = if i == 0 { u } else { x[i - 1] };
+                // The last capacitor only has its input resistor
+                let next = if i + 1 < self.order { x[i + 1] } else { x[i] };
+                (prev - x[i] - (x[i] - next)) / rc
+            } else {
+                // Unused stages decay, which keeps them out of the way of the solvers
+                -x[i] / rc
+            };
+        }
+        dx
+    }
+
+    fn output(&self, _u: f64, x: &[f64; RcLadder::MAX_ORDER]) -> f64 {
+        x[self.order - 1]
+    }
+}
+
+impl LinearCircuit<{ RcLadder::MAX_ORDER }> for RcLadder {
+    fn state_space(&self) -> StateSpace<{ RcLadder::MAX_ORDER }> {
+        let g = self.rc.recip();
+        let mut a = [[0.; RcLadder::MAX_ORDER]; RcLadder::MAX_ORDER];
+        for i in 0..RcLadder::MAX_ORDER {
+            a[i][i] = -g;
+            if i < self.order {
+                if i > 0 {
+                    a[i][i - 1] = g;
+                }
+                if i + 1 < self.order {
+                    a[i][i] -= g;
+                    a[i][i + 1] = g;
+                }
+            }
+        }
+        let mut b = [0.; RcLadder::MAX_ORDER];
+        b[0] = g;
+        let mut c = [0.; RcLadder::MAX_ORDER];
+        c[self.order - 1] = 1.;
+        StateSpace { a, b, c, d: 0. }
+    }
+}
+
+/// State of the ladder, holding the voltage of each capacitor.
+pub type RcLadderState = CircuitState<RcLadder, { RcLadder::MAX_ORDER }>;
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    use approx::assert_relative_eq;
+    use rustfft::num_complex::Complex;
+
+    use crate::lpf::test_utils::measure_gain;
+    use crate::ode::{Solver, Trapezoidal};
+    use crate::state_space::LinearCircuit;
+
+    use super::{RcLadder, RcLadderState};
+
+    fn binomial(n: usize, k: usize) -> f64 {
+        (0..k).fold(1., |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+    }
+
+    #[test]
+    fn test_rc_ladder_response() {
+        let fc = 1e3;
+        for order in 1..=RcLadder::MAX_ORDER {
+            let filter = RcLadder::new(fc, order);
+            let sys = filter.state_space();
+            assert_relative_eq!(sys.dc_gain(), 1., max_relative = 1e-9);
+            for freq in [fc / 4., fc, 4. * fc] {
+                let s = Complex::new(0., freq / fc);
+                let den = (0..=order)
+                    .map(|k| binomial(order + k, 2 * k) * s.powu(k as u32))
+                    .sum::<Complex<f64>>();
+                assert_relative_eq!(
+                    sys.frequency_response(freq).norm(),
+                    den.norm().recip(),
+                    max_relative = 1e-9
+                );
+            }
+            // The stages load each other, so that the gain at the cutoff of a single stage is
+            // lower than in an unloaded cascade
+            let gain = sys.frequency_response(fc).norm();
+            if order == 1 {
+                assert_relative_eq!(gain, FRAC_1_SQRT_2, max_relative = 1e-9);
+            } else {
+                assert!(gain < FRAC_1_SQRT_2.powi(order as i32));
+            }
+        }
+    }
+
+    #[test]
+    fn test_rc_ladder_simulation() {
+        let step = (4. * 44100f64).recip();
+        let fc = 1e3;
+        let filter = RcLadder::new(fc, 4);
+        let expected = filter.state_space().frequency_response(fc).norm();
+        let mut state = RcLadderState::new(Solver::Trapezoidal(Trapezoidal::default()));
+        let gain = measure_gain(fc, step, |x| {
+            state.set_v_in(x);
+            state.process(&filter, step)
+        });
+        assert_relative_eq!(gain, expected, max_relative = 1e-3);
+        assert!(!state.take_diverged());
+    }
+}
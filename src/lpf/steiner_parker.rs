@@ -0,0 +1,196 @@
+//! Steiner-Parker (Synthacon) multimode filter.
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::{saturate, SallenKey};
+
+/// Equal-component Sallen-Key whose resistors are diode pairs, and where the input is routed to
+/// three places with separate weights: the first diode pair for the lowpass response, the end of
+/// `C1` which is otherwise tied to the output for the bandpass response, and the end of `C2`
+/// which is otherwise tied to ground for the highpass response.
+///
+/// With `k` the gain of the op-amp and `s` normalized to the cutoff, the small-signal response
+/// is `k (lp + s bp + s (s + 2) hp) / (s² + (3 - k) s + 1)`, so the highpass input also brings in
+/// some of the bandpass response.
+///
+/// The current through each diode pair is the `tanh` of the voltage across it over `v_diode`,
+/// which slows the filter down on large signals.
+#[derive(Debug, Clone, Copy)]
+pub struct SteinerParker {
+    sallen_key: SallenKey,
+    weights: [f64; 3],
+    pub v_diode: f64,
+}
+
+impl SteinerParker {
+    pub fn new(fc: f64, q: f64) -> Self {
+        Self {
+            sallen_key: SallenKey::new(fc, q),
+            weights: [1., 0., 0.],
+            v_diode: 0.5,
+        }
+    }
+
+    pub fn set_fc(&mut self, fc: f64) {
+        self.sallen_key.set_fc(fc);
+    }
+
+    pub fn set_q(&mut self, q: f64) {
+        self.sallen_key.set_q(q);
+    }
+
+    /// Set how much of the input goes to the lowpass, bandpass and highpass inputs.
+    pub fn set_input_weights(&mut self, lowpass: f64, bandpass: f64, highpass: f64) {
+        self.weights = [lowpass, bandpass, highpass];
+    }
+
+    /// Current through a diode pair, scaled to the one of a resistor of the same small-signal
+    /// value.
+    fn diodes<T: Float>(&self, v: T) -> T {
+        let v_diode = T::from(self.v_diode).unwrap();
+        v_diode * (v / v_diode).tanh()
+    }
+
+    /// Voltages at the end of `C1` driven by the output and the bandpass input, and at the
+    /// op-amp input, above `C2` driven by the highpass input.
+    fn nodes<T: Float>(&self, u: T, x: &[T; 2]) -> (T, T) {
+        let [_, bp, hp] = self.weights.map(|w| T::from(w).unwrap() * u);
+        let v_b = x[1] + hp;
+        let v_f = self.v_out(v_b) + bp;
+        (v_f, v_b)
+    }
+
+    fn v_out<T: Float>(&self, v_b: T) -> T {
+        saturate(
+            self.sallen_key.vcc,
+            T::from(self.sallen_key.k).unwrap() * v_b,
+        )
+    }
+}
+
+impl Lerp for SteinerParker {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            sallen_key: self.sallen_key.lerp(&other.sallen_key, t),
+            weights: std::array::from_fn(|i| self.weights[i].lerp(&other.weights[i], t)),
+            v_diode: self.v_diode.lerp(&other.v_diode, t),
+        }
+    }
+}
+
+/// The state holds the voltages across `C1` and `C2`, as in [`SallenKey`].
+impl OdeSystem<2> for SteinerParker {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 2]) -> [T; 2] {
+        let rc = T::from(self.sallen_key.rc).unwrap();
+        let lp = T::from(self.weights[0]).unwrap() * u;
+        let (v_f, v_b) = self.nodes(u, x);
+        let v_a = x[0] + v_f;
+        let i_1 = self.diodes(lp - v_a);
+        let i_2 = self.diodes(v_a - v_b);
+        [(i_1 - i_2) / rc, i_2 / rc]
+    }
+
+    fn output(&self, u: f64, x: &[f64; 2]) -> f64 {
+        self.v_out(self.nodes(u, x).1)
+    }
+}
+
+/// Small-signal model, where the diode pairs behave as resistors.
+impl LinearCircuit<2> for SteinerParker {
+    fn state_space(&self) -> StateSpace<2> {
+        let (g, k) = (self.sallen_key.rc.recip(), self.sallen_key.k);
+        let [lp, bp, hp] = self.weights;
+        // v_a = v_c1 + k (v_c2 + hp u) + bp u, v_b = v_c2 + hp u
+        StateSpace {
+            a: [[-2. * g, (1. - 2. * k) * g], [g, (k - 1.) * g]],
+            b: [
+                (lp - 2. * bp + (1. - 2. * k) * hp) * g,
+                (bp + (k - 1.) * hp) * g,
+            ],
+            c: [0., k],
+            d: k * hp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use approx::assert_relative_eq;
+
+    use crate::lpf::SallenKeyState;
+    use crate::ode::Solver;
+    use crate::state_space::LinearCircuit;
+
+    use super::SteinerParker;
+
+    #[test]
+    fn test_steiner_parker_modes() {
+        let (fc, q) = (1e3, 2f64);
+        let k = 3. - q.recip();
+        let mut filter = SteinerParker::new(fc, q);
+        for (weights, numerator) in [
+            ([1., 0., 0.], (0., 0.)),
+            ([0., 1., 0.], (0., 1.)),
+            ([0., 0., 1.], (-1., 2.)),
+        ] {
+            filter.set_input_weights(weights[0], weights[1], weights[2]);
+            let sys = filter.state_space();
+            for w in [0.25, 1., 4.] {
+                let (re, im) = (numerator.0 * w * w + weights[0], numerator.1 * w);
+                let expected = k * f64::hypot(re, im) / f64::hypot(1. - w * w, w / q);
+                assert_relative_eq!(
+                    sys.frequency_response(w * fc).norm(),
+                    expected,
+                    max_relative = 1e-9
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_steiner_parker_step_response() {
+        let step = (4. * 44100f64).recip();
+        let (fc, q) = (1e3, 4f64);
+        let k = 3. - q.recip();
+        let filter = SteinerParker::new(fc, q);
+        // Overshoot of the step response, time of its first peak, and time between its first
+        // two peaks
+        let ring = |level: f64| {
+            let mut state = SallenKeyState::new(Solver::Rk4);
+            state.set_v_in(level);
+            let mut out = [0.; 3];
+            let mut max = 0f64;
+            let mut peaks = vec![];
+            for n in 0..2000 {
+                out = [out[1], out[2], state.process(&filter, step)];
+                assert!(out[2].abs() <= filter.sallen_key.vcc);
+                max = max.max(out[2]);
+                if out[1] > out[0] && out[1] > out[2] {
+                    peaks.push((n - 1) as f64 * step);
+                }
+            }
+            (max / (k * level) - 1., peaks[0], peaks[1] - peaks[0])
+        };
+
+        // Small steps ring as the linear second-order system
+        let zeta = 0.5 / q;
+        let damped = f64::sqrt(1. - zeta * zeta);
+        let (overshoot, rise, period) = ring(1e-3);
+        assert_relative_eq!(
+            overshoot,
+            f64::exp(-PI * zeta / damped),
+            max_relative = 1e-2
+        );
+        assert_relative_eq!(period, (fc * damped).recip(), max_relative = 1e-2);
+
+        // Large ones saturate the diodes, which limits how fast the capacitors charge
+        let (_, rise_large, _) = ring(1.);
+        assert!(rise_large > 1.2 * rise);
+    }
+}
@@ -0,0 +1,236 @@
+//! Two-integrator state variable filter, as in the Oberheim SEM.
+
+use std::f64::consts::TAU;
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::CircuitState;
+
+/// Every output of the filter at once.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SvfOutputs {
+    pub lowpass: f64,
+    pub bandpass: f64,
+    pub highpass: f64,
+    pub notch: f64,
+}
+
+/// State variable filter made of two integrators in a loop: the highpass output is the input
+/// minus the lowpass and damped bandpass feedback, and is integrated into the bandpass, which is
+/// integrated into the lowpass.
+///
+/// The integrators are OTAs, whose output current saturates softly at `v_sat`. Resonance lowers
+/// the damping by feeding the bandpass back through another saturating stage, so that past the
+/// point where the damping becomes negative, the filter settles into a bounded self-oscillation.
+#[derive(Debug, Clone, Copy)]
+pub struct Svf {
+    wc: f64,
+    resonance: f64,
+    morph: f64,
+    pub v_sat: f64,
+}
+
+impl Svf {
+    /// Resonance at which the damping cancels out and the filter self-oscillates.
+    pub const SELF_OSCILLATION: f64 = 1.;
+
+    pub fn new(fc: f64, q: f64) -> Self {
+        let mut this = Self {
+            wc: TAU * fc,
+            resonance: 0.,
+            morph: 0.,
+            v_sat: 4.,
+        };
+        this.set_q(q);
+        this
+    }
+
+    pub fn set_fc(&mut self, fc: f64) {
+        self.wc = TAU * fc;
+    }
+
+    /// Set the small-signal Q, which is `1 / (2 (1 - resonance))`.
+    pub fn set_q(&mut self, q: f64) {
+        self.resonance = 1. - 0.5 / q.max(0.5);
+    }
+
+    /// Set the resonance directly, which reaches self-oscillation at [`Self::SELF_OSCILLATION`].
+    pub fn set_resonance(&mut self, resonance: f64) {
+        self.resonance = resonance.max(0.);
+    }
+
+    /// Crossfade of the output from lowpass at 0 to highpass at 1, going through a notch 6 dB
+    /// down at 0.5.
+    pub fn set_morph(&mut self, morph: f64) {
+        self.morph = morph.clamp(0., 1.);
+    }
+
+    fn sat<T: Float>(&self, x: T) -> T {
+        let v_sat = T::from(self.v_sat).unwrap();
+        v_sat * (x / v_sat).tanh()
+    }
+
+    fn highpass<T: Float>(&self, u: T, bp: T, lp: T) -> T {
+        let two = T::from(2).unwrap();
+        u - lp - two * (bp - T::from(self.resonance).unwrap() * self.sat(bp))
+    }
+
+    /// Every output for the input `u` and the state `x`.
+    pub fn outputs(&self, u: f64, x: &[f64; 2]) -> SvfOutputs {
+        let [bandpass, lowpass] = *x;
+        let highpass = self.highpass(u, bandpass, lowpass);
+        SvfOutputs {
+            lowpass,
+            bandpass,
+            highpass,
+            notch: lowpass + highpass,
+        }
+    }
+}
+
+impl Lerp for Svf {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            wc: self.wc.lerp(&other.wc, t),
+            resonance: self.resonance.lerp(&other.resonance, t),
+            morph: self.morph.lerp(&other.morph, t),
+            v_sat: self.v_sat.lerp(&other.v_sat, t),
+        }
+    }
+}
+
+/// The state holds the bandpass and lowpass integrator outputs.
+impl OdeSystem<2> for Svf {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 2]) -> [T; 2] {
+        let [bp, lp] = *x;
+        let wc = T::from(self.wc).unwrap();
+        let hp = self.highpass(u, bp, lp);
+        [wc * self.sat(hp), wc * self.sat(bp)]
+    }
+
+    /// Morphed output, see [`Svf::set_morph`].
+    fn output(&self, u: f64, x: &[f64; 2]) -> f64 {
+        let out = self.outputs(u, x);
+        out.lowpass.lerp(&out.highpass, self.morph)
+    }
+}
+
+/// Small-signal model of the morphed output, where nothing saturates.
+impl LinearCircuit<2> for Svf {
+    fn state_space(&self) -> StateSpace<2> {
+        let (w, m) = (self.wc, self.morph);
+        let damping = 2. * (1. - self.resonance);
+        StateSpace {
+            a: [[-damping * w, -w], [w, 0.]],
+            b: [w, 0.],
+            c: [-m * damping, 1. - 2. * m],
+            d: m,
+        }
+    }
+}
+
+/// State of the filter, holding the bandpass and lowpass outputs of the two integrators.
+pub type SvfState = CircuitState<Svf, 2>;
+
+impl CircuitState<Svf, 2> {
+    /// Every output of the filter at the last processed sample.
+    pub fn outputs(&self, filter: &Svf) -> SvfOutputs {
+        filter.outputs(self.v_in, &self.x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::lpf::test_utils::{measure_gain, ring};
+    use crate::ode::{Solver, Trapezoidal};
+    use crate::state_space::LinearCircuit;
+
+    use super::{Svf, SvfOutputs, SvfState};
+
+    #[test]
+    fn test_svf_outputs() {
+        let step = (4. * 44100f64).recip();
+        let (fc, q) = (1e3, 2.);
+        let filter = Svf::new(fc, q);
+        // Steady-state gain of every output, for a small sine wave at `freq` Hz
+        let gains = |freq: f64| {
+            let outputs: [fn(SvfOutputs) -> f64; 4] = [
+                |out| out.lowpass,
+                |out| out.bandpass,
+                |out| out.highpass,
+                |out| out.notch,
+            ];
+            outputs.map(|output| {
+                let amplitude = 1e-2;
+                let mut state = SvfState::new(Solver::Rk4);
+                measure_gain(freq, step, |x| {
+                    state.set_v_in(amplitude * x);
+                    state.process(&filter, step);
+                    output(state.outputs(&filter)) / amplitude
+                })
+            })
+        };
+
+        // At the cutoff, every output but the notch has a gain of Q
+        let [lowpass, bandpass, highpass, notch] = gains(fc);
+        assert_relative_eq!(lowpass, q, max_relative = 1e-3);
+        assert_relative_eq!(bandpass, q, max_relative = 1e-3);
+        assert_relative_eq!(highpass, q, max_relative = 1e-2);
+        // The input is held over each step while it feeds straight into the notch, which limits
+        // its depth
+        assert!(notch < 0.05);
+
+        for freq in [fc / 4., 4. * fc] {
+            let w = freq / fc;
+            let den = f64::hypot(1. - w * w, w / q);
+            let [lowpass, bandpass, highpass, notch] = gains(freq);
+            assert_relative_eq!(lowpass, den.recip(), max_relative = 1e-2);
+            assert_relative_eq!(bandpass, w / den, max_relative = 1e-2);
+            assert_relative_eq!(highpass, w * w / den, max_relative = 1e-2);
+            assert_relative_eq!(notch, (1. - w * w).abs() / den, max_relative = 1e-2);
+        }
+    }
+
+    #[test]
+    fn test_svf_morph() {
+        let (fc, q) = (1e3, 2.);
+        let mut filter = Svf::new(fc, q);
+        let at = |filter: &Svf, freq: f64| filter.state_space().frequency_response(freq).norm();
+        assert_relative_eq!(at(&filter, 0.), 1.);
+        filter.set_morph(1.);
+        assert_relative_eq!(at(&filter, 1e6), 1., max_relative = 1e-6);
+        filter.set_morph(0.5);
+        assert!(at(&filter, fc) < 1e-9);
+        assert_relative_eq!(at(&filter, 0.), 0.5);
+    }
+
+    #[test]
+    fn test_svf_self_oscillation() {
+        let step = (4. * 44100f64).recip();
+        let fc = 1e3;
+        // Ring left after a short kick, over 100 periods
+        let kick = |resonance: f64| {
+            let mut filter = Svf::new(fc, 0.5);
+            filter.set_resonance(resonance);
+            let mut state = SvfState::new(Solver::Trapezoidal(Trapezoidal::default()));
+            ring(step, 100. / fc, step, 2, |x| {
+                state.set_v_in(x);
+                state.process(&filter, step)
+            })
+        };
+
+        let decaying = kick(0.95);
+        assert!(decaying.last < 0.1 * decaying.first);
+
+        // The oscillation builds up until the saturation holds it
+        let growing = kick(1.05);
+        assert!(growing.last > 10. * growing.first);
+        assert!(growing.last < 2. * Svf::new(fc, 0.5).v_sat);
+    }
+}
@@ -0,0 +1,160 @@
+//! Measurements shared by the tests of the circuit models.
+
+use std::f64::consts::TAU;
+
+use rustfft::num_complex::Complex;
+
+/// Steady-state gain of `process` for a sine wave at `freq` Hz, sampled every `step` seconds.
+/// `process` takes each sample of the unit sine wave and returns the output of the circuit.
+pub fn measure_gain(freq: f64, step: f64, mut process: impl FnMut(f64) -> f64) -> f64 {
+    let periods = 50.;
+    let len = (periods / (freq * step)).round() as usize;
+    let (mut re, mut im) = (0., 0.);
+    for n in 0..2 * len {
+        let phase = TAU * freq * n as f64 * step;
+        let y = process(phase.sin());
+        // Skip the transient, then correlate over a whole number of periods
+        if n >= len {
+            re += y * phase.sin();
+            im += y * phase.cos();
+        }
+    }
+    2. * f64::hypot(re, im) / len as f64
+}
+
+/// Time and value of every peak of the response of `process` to an impulse of area `area`, over
+/// `duration` seconds sampled every `step` seconds. The impulse is held over the first step.
+pub fn impulse_peaks(
+    step: f64,
+    duration: f64,
+    area: f64,
+    mut process: impl FnMut(f64) -> f64,
+) -> Vec<(f64, f64)> {
+    let mut out = [0.; 3];
+    let mut peaks = vec![];
+    for n in 0..(duration / step) as usize {
+        let u = if n == 0 { area / step } else { 0. };
+        out = [out[1], out[2], process(u)];
+        if n >= 2 && out[1] > out[0] && out[1] > out[2] {
+            peaks.push(((n - 1) as f64 * step, out[1]));
+        }
+    }
+    peaks
+}
+
+/// Ring left in the response to an impulse, from its peaks.
+#[derive(Debug, Clone, Copy)]
+pub struct Ring {
+    /// Level of the first and the last peak taken into account.
+    pub first: f64,
+    pub last: f64,
+    /// Frequency of the ring, from the time between its peaks.
+    pub freq: f64,
+    /// Growth rate of the envelope, negative when the ring dies out.
+    pub rate: f64,
+}
+
+/// Sum up the ring of `process` after an impulse, from the peaks found by [`impulse_peaks`] with
+/// the same arguments. The first `skip` peaks are left out, for the ring to take over from the
+/// rest of the response.
+pub fn ring(
+    step: f64,
+    duration: f64,
+    area: f64,
+    skip: usize,
+    process: impl FnMut(f64) -> f64,
+) -> Ring {
+    let peaks = impulse_peaks(step, duration, area, process);
+    let (first, last) = (peaks[skip], peaks[peaks.len() - 1]);
+    let span = last.0 - first.0;
+    Ring {
+        first: first.1,
+        last: last.1,
+        freq: (peaks.len() - 1 - skip) as f64 / span,
+        rate: (last.1 / first.1).ln() / span,
+    }
+}
+
+/// Element of a netlist for [`nodal_response`], between numbered nodes where 0 is ground.
+#[derive(Debug, Clone, Copy)]
+pub enum Element {
+    Resistor(usize, usize, f64),
+    Capacitor(usize, usize, f64),
+    /// Ideal op-amp, with its non-inverting input, its inverting input and its output.
+    OpAmp(usize, usize, usize),
+}
+
+/// Response at node `output` of the network made of `elements`, with node 1 driven by a unit
+/// voltage source at `freq` Hz. The network is solved by modified nodal analysis, as in C.-W. Ho,
+/// A. E. Ruehli and P. A. Brennan, "The modified nodal approach to network analysis", IEEE Trans.
+/// Circuits Syst., 1975: the current delivered by the source and by the output of each op-amp are
+/// unknowns next to the node voltages, with an equation each for the voltage they hold.
+pub fn nodal_response(elements: &[Element], output: usize, freq: f64) -> Complex<f64> {
+    let s = Complex::new(0., TAU * freq);
+    let nodes = elements
+        .iter()
+        .map(|e| match *e {
+            Element::Resistor(a, b, _) | Element::Capacitor(a, b, _) => a.max(b),
+            Element::OpAmp(p, m, o) => p.max(m).max(o),
+        })
+        .max()
+        .unwrap();
+    let op_amps = elements
+        .iter()
+        .filter(|e| matches!(e, Element::OpAmp(..)))
+        .count();
+    let size = nodes + 1 + op_amps;
+    let mut m = vec![vec![Complex::new(0., 0.); size + 1]; size];
+    // Node `n` is unknown `n - 1`, and ground isn't one. The current of the source comes next,
+    // then the output current of each op-amp
+    let mut stamp = |row: usize, col: usize, y: Complex<f64>| {
+        if row > 0 && col > 0 {
+            m[row - 1][col - 1] += y;
+        }
+    };
+    let mut extra = nodes + 2;
+    for e in elements {
+        let (a, b, y) = match *e {
+            Element::Resistor(a, b, r) => (a, b, Complex::new(r.recip(), 0.)),
+            Element::Capacitor(a, b, c) => (a, b, s * c),
+            Element::OpAmp(p, n, o) => {
+                let one = Complex::new(1., 0.);
+                stamp(o, extra, one);
+                stamp(extra, p, one);
+                stamp(extra, n, -one);
+                extra += 1;
+                continue;
+            }
+        };
+        stamp(a, a, y);
+        stamp(b, b, y);
+        stamp(a, b, -y);
+        stamp(b, a, -y);
+    }
+    // The source drives node 1
+    let source = nodes;
+    m[0][source] = Complex::new(1., 0.);
+    m[source][0] = Complex::new(1., 0.);
+    m[source][size] = Complex::new(1., 0.);
+
+    // Gaussian elimination with partial pivoting, then back substitution
+    for k in 0..size {
+        let pivot = (k..size)
+            .max_by(|&i, &j| m[i][k].norm().total_cmp(&m[j][k].norm()))
+            .unwrap();
+        m.swap(k, pivot);
+        for i in k + 1..size {
+            let f = m[i][k] / m[k][k];
+            for j in k..=size {
+                let d = f * m[k][j];
+                m[i][j] -= d;
+            }
+        }
+    }
+    let mut x = vec![Complex::new(0., 0.); size];
+    for k in (0..size).rev() {
+        let sum = (k + 1..size).fold(m[k][size], |sum, j| sum - m[k][j] * x[j]);
+        x[k] = sum / m[k][k];
+    }
+    x[output - 1]
+}
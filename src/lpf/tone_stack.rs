@@ -0,0 +1,476 @@
+//! Passive Fender/Marshall style tone stack, and the Vox one.
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::CircuitState;
+
+/// Smallest fraction of a pot's track between its wiper and either end, which stands for the
+/// contact resistance and keeps the pots from shorting out a node.
+const MIN_FRACTION: f64 = 1e-3;
+
+/// Resistance law of a pot, mapping the position of its wiper to the fraction of the track
+/// between the wiper and the start.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Taper {
+    Linear,
+    /// Audio taper, with 10 % of the resistance at half rotation.
+    #[default]
+    Log,
+}
+
+impl Taper {
+    pub fn fraction(&self, position: f64) -> f64 {
+        let position = position.clamp(0., 1.);
+        let fraction = match self {
+            Self::Linear => position,
+            Self::Log => (81f64.powf(position) - 1.) / 80.,
+        };
+        fraction.clamp(MIN_FRACTION, 1. - MIN_FRACTION)
+    }
+}
+
+/// Component values of the tone stack, with the treble, bass and mid pots as `r1`, `r2` and `r3`,
+/// and the slope resistor as `r4`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneStackComponents {
+    pub r1: f64,
+    pub r2: f64,
+    pub r3: f64,
+    pub r4: f64,
+    pub c1: f64,
+    pub c2: f64,
+    pub c3: f64,
+}
+
+impl ToneStackComponents {
+    /// Fender '59 Bassman (5F6-A).
+    pub const FENDER_BASSMAN: Self = Self {
+        r1: 250e3,
+        r2: 1e6,
+        r3: 25e3,
+        r4: 56e3,
+        c1: 250e-12,
+        c2: 20e-9,
+        c3: 20e-9,
+    };
+
+    /// Marshall JCM800.
+    pub const MARSHALL_JCM800: Self = Self {
+        r1: 220e3,
+        r2: 1e6,
+        r3: 22e3,
+        r4: 33e3,
+        c1: 470e-12,
+        c2: 22e-9,
+        c3: 22e-9,
+    };
+}
+
+/// Tone stack where the input feeds `C1` into the top of the treble pot, whose wiper is the
+/// output, and the slope resistor `R4` into both `C2`, which goes to the bottom of the treble pot,
+/// and `C3`, which goes to the wiper of the mid pot. The bass pot, wired as a variable resistor,
+/// joins the bottom of the treble pot to the top of the mid pot, whose bottom is grounded.
+///
+/// Every pot is set by the position of its wiper, which goes through the `taper` law. The output
+/// is unloaded.
+#[derive(Debug, Clone, Copy)]
+pub struct ToneStack {
+    pub components: ToneStackComponents,
+    pub taper: Taper,
+    treble: f64,
+    mid: f64,
+    bass: f64,
+}
+
+impl ToneStack {
+    pub fn new(components: ToneStackComponents) -> Self {
+        Self {
+            components,
+            taper: Taper::default(),
+            treble: 0.5,
+            mid: 0.5,
+            bass: 0.5,
+        }
+    }
+
+    pub fn set_treble(&mut self, position: f64) {
+        self.treble = position;
+    }
+
+    pub fn set_mid(&mut self, position: f64) {
+        self.mid = position;
+    }
+
+    pub fn set_bass(&mut self, position: f64) {
+        self.bass = position;
+    }
+
+    /// Fractions of the treble, mid and bass pots under their wipers.
+    pub fn pot_fractions(&self) -> [f64; 3] {
+        [self.treble, self.mid, self.bass].map(|p| self.taper.fraction(p))
+    }
+
+    /// Conductances of the treble pot, of the path from its bottom through the bass pot to the mid
+    /// wiper, and of the mid pot below its wiper.
+    fn conductances<T: Float>(&self) -> [T; 3] {
+        let ToneStackComponents { r1, r2, r3, .. } = self.components;
+        let [_, m, l] = self.pot_fractions();
+        [r1, l * r2 + (1. - m) * r3, m * r3].map(|r| T::from(r.recip()).unwrap())
+    }
+
+    /// Voltages at the top and bottom of the treble pot, and at the mid wiper.
+    fn nodes<T: Float>(&self, u: T, x: &[T; 3]) -> [T; 3] {
+        let [v_c1, v_c2, v_c3] = *x;
+        let [g_treble, _, g_mid] = self.conductances::<T>();
+        let g4 = T::from(self.components.r4.recip()).unwrap();
+        // Taken together, the node between R4, C2 and C3 and the far sides of both capacitors
+        // only have current coming in through R4 and the treble pot, and going out through the
+        // bottom of the mid pot
+        let v_top = u - v_c1;
+        let v_a = (u * g4 + (v_top + v_c2) * g_treble + v_c3 * g_mid) / (g4 + g_treble + g_mid);
+        [v_top, v_a - v_c2, v_a - v_c3]
+    }
+}
+
+impl Lerp for ToneStack {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            components: other.components,
+            taper: other.taper,
+            treble: self.treble.lerp(&other.treble, t),
+            mid: self.mid.lerp(&other.mid, t),
+            bass: self.bass.lerp(&other.bass, t),
+        }
+    }
+}
+
+/// The state holds the voltages across `C1`, `C2` and `C3`, each from the input side.
+impl OdeSystem<3> for ToneStack {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 3]) -> [T; 3] {
+        let [g_treble, g_bass, g_mid] = self.conductances::<T>();
+        let [v_top, v_bottom, v_wiper] = self.nodes(u, x);
+        let i_treble = (v_top - v_bottom) * g_treble;
+        let i_bass = (v_bottom - v_wiper) * g_bass;
+        let i_c2 = i_bass - i_treble;
+        let i_c3 = v_wiper * g_mid - i_bass;
+        let [c1, c2, c3] = [self.components.c1, self.components.c2, self.components.c3]
+            .map(|c| T::from(c).unwrap());
+        [i_treble / c1, i_c2 / c2, i_c3 / c3]
+    }
+
+    fn output(&self, u: f64, x: &[f64; 3]) -> f64 {
+        let [t, ..] = self.pot_fractions();
+        let [v_top, v_bottom, _] = self.nodes(u, x);
+        t * v_top + (1. - t) * v_bottom
+    }
+}
+
+/// The network is linear, so its state-space model is read off the derivative and the output.
+impl LinearCircuit<3> for ToneStack {
+    fn state_space(&self) -> StateSpace<3> {
+        let mut a = [[0.; 3]; 3];
+        let mut c = [0.; 3];
+        for j in 0..3 {
+            let mut x = [0.; 3];
+            x[j] = 1.;
+            let dx = self.derivative(0., &x);
+            for i in 0..3 {
+                a[i][j] = dx[i];
+            }
+            c[j] = self.output(0., &x);
+        }
+        StateSpace {
+            a,
+            b: self.derivative(1., &[0.; 3]),
+            c,
+            d: self.output(1., &[0.; 3]),
+        }
+    }
+}
+
+/// State of the tone stacks, holding the voltage across each of their capacitors.
+pub type ToneStackState<F = ToneStack> = CircuitState<F, 3>;
+
+/// Tone stack of the Vox AC30 top boost, which has no mid control. The input feeds `c_treble`
+/// into the top of the treble pot, whose wiper is the output, and `r_slope` into both `c_bass`,
+/// which goes to the bottom of the treble pot, and `c_mid`, which goes to the top of the fixed
+/// resistor `r_mid` to ground. The bass pot, wired as a variable resistor, joins the bottom of the
+/// treble pot to the top of `r_mid`.
+///
+/// Both pots are set by the position of their wiper, which goes through the `taper` law. The
+/// output is unloaded.
+#[derive(Debug, Clone, Copy)]
+pub struct VoxToneStack {
+    pub taper: Taper,
+    pub r_treble: f64,
+    pub r_bass: f64,
+    pub r_mid: f64,
+    pub r_slope: f64,
+    pub c_treble: f64,
+    pub c_bass: f64,
+    pub c_mid: f64,
+    treble: f64,
+    bass: f64,
+}
+
+impl Default for VoxToneStack {
+    fn default() -> Self {
+        Self {
+            taper: Taper::default(),
+            r_treble: 1e6,
+            r_bass: 1e6,
+            r_mid: 10e3,
+            r_slope: 100e3,
+            c_treble: 50e-12,
+            c_bass: 22e-9,
+            c_mid: 22e-9,
+            treble: 0.5,
+            bass: 0.5,
+        }
+    }
+}
+
+impl VoxToneStack {
+    pub fn set_treble(&mut self, position: f64) {
+        self.treble = position;
+    }
+
+    pub fn set_bass(&mut self, position: f64) {
+        self.bass = position;
+    }
+
+    /// Fractions of the treble and bass pots under their wipers.
+    pub fn pot_fractions(&self) -> [f64; 2] {
+        [self.treble, self.bass].map(|p| self.taper.fraction(p))
+    }
+
+    /// Conductances of the treble pot, of the bass pot and of the mid resistor.
+    fn conductances<T: Float>(&self) -> [T; 3] {
+        let [_, l] = self.pot_fractions();
+        [self.r_treble, l * self.r_bass, self.r_mid].map(|r| T::from(r.recip()).unwrap())
+    }
+
+    /// Voltages at the top and bottom of the treble pot, and at the top of the mid resistor.
+    fn nodes<T: Float>(&self, u: T, x: &[T; 3]) -> [T; 3] {
+        let [v_c1, v_c2, v_c3] = *x;
+        let [g_treble, _, g_mid] = self.conductances::<T>();
+        let g_slope = T::from(self.r_slope.recip()).unwrap();
+        // As in the Fender stack, the node behind the slope resistor and the far sides of both
+        // capacitors only have current coming in through the slope resistor and the treble pot,
+        // and going out through the mid resistor
+        let v_top = u - v_c1;
+        let v_a =
+            (u * g_slope + (v_top + v_c2) * g_treble + v_c3 * g_mid) / (g_slope + g_treble + g_mid);
+        [v_top, v_a - v_c2, v_a - v_c3]
+    }
+}
+
+impl Lerp for VoxToneStack {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            treble: self.treble.lerp(&other.treble, t),
+            bass: self.bass.lerp(&other.bass, t),
+            ..*other
+        }
+    }
+}
+
+/// The state holds the voltages across `c_treble`, `c_bass` and `c_mid`, each from the input side.
+impl OdeSystem<3> for VoxToneStack {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 3]) -> [T; 3] {
+        let [g_treble, g_bass, g_mid] = self.conductances::<T>();
+        let [v_top, v_bottom, v_mid] = self.nodes(u, x);
+        let i_treble = (v_top - v_bottom) * g_treble;
+        let i_bass = (v_bottom - v_mid) * g_bass;
+        let i_c2 = i_bass - i_treble;
+        let i_c3 = v_mid * g_mid - i_bass;
+        let [c1, c2, c3] = [self.c_treble, self.c_bass, self.c_mid].map(|c| T::from(c).unwrap());
+        [i_treble / c1, i_c2 / c2, i_c3 / c3]
+    }
+
+    fn output(&self, u: f64, x: &[f64; 3]) -> f64 {
+        let [t, _] = self.pot_fractions();
+        let [v_top, v_bottom, _] = self.nodes(u, x);
+        t * v_top + (1. - t) * v_bottom
+    }
+}
+
+/// The network is linear, so its state-space model is read off the derivative and the output.
+impl LinearCircuit<3> for VoxToneStack {
+    fn state_space(&self) -> StateSpace<3> {
+        let mut a = [[0.; 3]; 3];
+        let mut c = [0.; 3];
+        for j in 0..3 {
+            let mut x = [0.; 3];
+            x[j] = 1.;
+            let dx = self.derivative(0., &x);
+            for i in 0..3 {
+                a[i][j] = dx[i];
+            }
+            c[j] = self.output(0., &x);
+        }
+        StateSpace {
+            a,
+            b: self.derivative(1., &[0.; 3]),
+            c,
+            d: self.output(1., &[0.; 3]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use approx::assert_relative_eq;
+    use rustfft::num_complex::Complex;
+
+    use crate::lpf::test_utils::{measure_gain, nodal_response, Element};
+    use crate::ode::{Exponential, Solver};
+    use crate::state_space::LinearCircuit;
+
+    use super::{Taper, ToneStack, ToneStackComponents, ToneStackState, VoxToneStack};
+
+    /// Transfer function of the tone stack from D. T. Yeh and J. O. Smith, "Discretization of the
+    /// '59 Fender Bassman tone stack", DAFx 2006, with `t`, `m` and `l` the fractions of the
+    /// treble, mid and bass pots.
+    fn yeh_smith(c: &ToneStackComponents, t: f64, m: f64, l: f64, s: Complex<f64>) -> Complex<f64> {
+        let ToneStackComponents {
+            r1,
+            r2,
+            r3,
+            r4,
+            c1,
+            c2,
+            c3,
+        } = *c;
+        let b1 = t * c1 * r1 + m * c3 * r3 + l * (c1 * r2 + c2 * r2) + (c1 * r3 + c2 * r3);
+        let b2 = t * (c1 * c2 * r1 * r4 + c1 * c3 * r1 * r4)
+            - m * m * (c1 * c3 * r3 * r3 + c2 * c3 * r3 * r3)
+            + m * (c1 * c3 * r1 * r3 + c1 * c3 * r3 * r3 + c2 * c3 * r3 * r3)
+            + l * (c1 * c2 * r1 * r2 + c1 * c2 * r2 * r4 + c1 * c3 * r2 * r4)
+            + l * m * (c1 * c3 * r2 * r3 + c2 * c3 * r2 * r3)
+            + (c1 * c2 * r1 * r3 + c1 * c2 * r3 * r4 + c1 * c3 * r3 * r4);
+        let c123 = c1 * c2 * c3;
+        let b3 = l * m * c123 * (r1 * r2 * r3 + r2 * r3 * r4)
+            - m * m * c123 * (r1 * r3 * r3 + r3 * r3 * r4)
+            + m * c123 * (r1 * r3 * r3 + r3 * r3 * r4)
+            + t * c123 * r1 * r3 * r4
+            - t * m * c123 * r1 * r3 * r4
+            + t * l * c123 * r1 * r2 * r4;
+        let a1 = (c1 * r1 + c1 * r3 + c2 * r3 + c2 * r4 + c3 * r4)
+            + m * c3 * r3
+            + l * (c1 * r2 + c2 * r2);
+        let a2 = m
+            * (c1 * c3 * r1 * r3 - c2 * c3 * r3 * r4 + c1 * c3 * r3 * r3 + c2 * c3 * r3 * r3)
+            + l * m * (c1 * c3 * r2 * r3 + c2 * c3 * r2 * r3)
+            - m * m * (c1 * c3 * r3 * r3 + c2 * c3 * r3 * r3)
+            + l * (c1 * c2 * r2 * r4 + c1 * c2 * r1 * r2 + c1 * c3 * r2 * r4 + c2 * c3 * r2 * r4)
+            + (c1 * c2 * r1 * r4
+                + c1 * c3 * r1 * r4
+                + c1 * c2 * r3 * r4
+                + c1 * c2 * r1 * r3
+                + c1 * c3 * r3 * r4
+                + c2 * c3 * r3 * r4);
+        let a3 = l * m * c123 * (r1 * r2 * r3 + r2 * r3 * r4)
+            - m * m * c123 * (r1 * r3 * r3 + r3 * r3 * r4)
+            + m * c123 * (r3 * r3 * r4 + r1 * r3 * r3 - r1 * r3 * r4)
+            + l * c123 * r1 * r2 * r4
+            + c123 * r1 * r3 * r4;
+        (b1 * s + b2 * s * s + b3 * s * s * s) / (1. + a1 * s + a2 * s * s + a3 * s * s * s)
+    }
+
+    #[test]
+    fn test_taper() {
+        assert_relative_eq!(Taper::Linear.fraction(0.5), 0.5);
+        assert_relative_eq!(Taper::Log.fraction(0.5), 0.1);
+        assert_relative_eq!(Taper::Log.fraction(1.), 1. - 1e-3);
+        assert_relative_eq!(Taper::Log.fraction(-1.), 1e-3);
+    }
+
+    #[test]
+    fn test_tone_stack_response() {
+        for components in [
+            ToneStackComponents::FENDER_BASSMAN,
+            ToneStackComponents::MARSHALL_JCM800,
+        ] {
+            let mut filter = ToneStack::new(components);
+            for (treble, mid, bass) in [(0.5, 0.5, 0.5), (0.2, 0.9, 0.7), (1., 0., 0.3)] {
+                filter.set_treble(treble);
+                filter.set_mid(mid);
+                filter.set_bass(bass);
+                let [t, m, l] = filter.pot_fractions();
+                let sys = filter.state_space();
+                for freq in [20., 100., 500., 2e3, 10e3] {
+                    let expected = yeh_smith(&components, t, m, l, Complex::new(0., TAU * freq));
+                    let response = sys.frequency_response(freq);
+                    assert_relative_eq!(response.re, expected.re, epsilon = 1e-9);
+                    assert_relative_eq!(response.im, expected.im, epsilon = 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_vox_tone_stack() {
+        let mut filter = VoxToneStack::default();
+        // With its mid pot at full rotation, the Fender stack is the Vox one, so that the
+        // transfer function of Yeh and Smith holds with the mid resistor standing for the pot
+        let components = ToneStackComponents {
+            r1: filter.r_treble,
+            r2: filter.r_bass,
+            r3: filter.r_mid,
+            r4: filter.r_slope,
+            c1: filter.c_treble,
+            c2: filter.c_bass,
+            c3: filter.c_mid,
+        };
+        for (treble, bass) in [(0.5, 0.5), (0.9, 0.1), (0.2, 1.)] {
+            filter.set_treble(treble);
+            filter.set_bass(bass);
+            let [t, l] = filter.pot_fractions();
+            // Input, top of the treble pot, output, bottom of the treble pot, node behind the
+            // slope resistor and top of the mid resistor
+            let netlist = [
+                Element::Capacitor(1, 2, filter.c_treble),
+                Element::Resistor(2, 3, (1. - t) * filter.r_treble),
+                Element::Resistor(3, 4, t * filter.r_treble),
+                Element::Resistor(1, 5, filter.r_slope),
+                Element::Capacitor(5, 4, filter.c_bass),
+                Element::Capacitor(5, 6, filter.c_mid),
+                Element::Resistor(4, 6, l * filter.r_bass),
+                Element::Resistor(6, 0, filter.r_mid),
+            ];
+            let sys = filter.state_space();
+            for freq in [20., 100., 500., 2e3, 10e3] {
+                let response = sys.frequency_response(freq);
+                let expected = yeh_smith(&components, t, 1., l, Complex::new(0., TAU * freq));
+                assert_relative_eq!(response.re, expected.re, epsilon = 1e-9);
+                assert_relative_eq!(response.im, expected.im, epsilon = 1e-9);
+                let expected = nodal_response(&netlist, 3, freq);
+                assert_relative_eq!(response.re, expected.re, epsilon = 1e-9);
+                assert_relative_eq!(response.im, expected.im, epsilon = 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tone_stack_simulation() {
+        let step = (4. * 44100f64).recip();
+        let mut filter = ToneStack::new(ToneStackComponents::FENDER_BASSMAN);
+        filter.set_mid(0.2);
+        for freq in [100., 500., 5e3] {
+            let expected = filter.state_space().frequency_response(freq).norm();
+            let mut state = ToneStackState::new(Solver::Exponential(Exponential::default()));
+            let gain = measure_gain(freq, step, |x| {
+                state.set_v_in(x);
+                state.process(&filter, step)
+            });
+            assert_relative_eq!(gain, expected, max_relative = 1e-2);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Twin-T notch, and the resonator made by putting it in the feedback path of an op-amp.
+
+use std::f64::consts::TAU;
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::CircuitState;
+
+/// Passive twin-T network: a lowpass T of `R1` and `R2` in series with `C3` to ground from the
+/// node between them, in parallel with a highpass T of `C1` and `C2` in series with `R3` to ground
+/// from the node between them. The output is unloaded.
+///
+/// When balanced, with `R3 = R / 2` and `C3 = 2 C`, the two paths cancel out at `1 / (2π R C)`
+/// for a notch of infinite depth.
+#[derive(Debug, Clone, Copy)]
+pub struct TwinT {
+    pub r1: f64,
+    pub r2: f64,
+    pub r3: f64,
+    pub c1: f64,
+    pub c2: f64,
+    pub c3: f64,
+}
+
+impl TwinT {
+    pub fn new(r1: f64, r2: f64, r3: f64, c1: f64, c2: f64, c3: f64) -> Self {
+        Self {
+            r1,
+            r2,
+            r3,
+            c1,
+            c2,
+            c3,
+        }
+    }
+
+    /// Balanced network, with a notch at `1 / (2π r c)`.
+    pub fn balanced(r: f64, c: f64) -> Self {
+        Self::new(r, r, r / 2., c, c, 2. * c)
+    }
+
+    /// Notch frequency of the balanced network with the series components `R1` and `C1`.
+    pub fn fc(&self) -> f64 {
+        (TAU * self.r1 * self.c1).recip()
+    }
+
+    fn conductances<T: Float>(&self) -> [T; 3] {
+        [self.r1, self.r2, self.r3].map(|r| T::from(r.recip()).unwrap())
+    }
+
+    fn capacitors<T: Float>(&self) -> [T; 3] {
+        [self.c1, self.c2, self.c3].map(|c| T::from(c).unwrap())
+    }
+}
+
+impl Lerp for TwinT {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            r1: self.r1.lerp(&other.r1, t),
+            r2: self.r2.lerp(&other.r2, t),
+            r3: self.r3.lerp(&other.r3, t),
+            c1: self.c1.lerp(&other.c1, t),
+            c2: self.c2.lerp(&other.c2, t),
+            c3: self.c3.lerp(&other.c3, t),
+        }
+    }
+}
+
+/// The state holds the voltages across `C1`, from the input, `C2`, towards the output, and `C3`.
+impl OdeSystem<3> for TwinT {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 3]) -> [T; 3] {
+        let [v_c1, v_c2, v_c3] = *x;
+        let [g1, g2, g3] = self.conductances::<T>();
+        let [c1, c2, c3] = self.capacitors::<T>();
+        let v_q = u - v_c1;
+        let v_out = v_q - v_c2;
+        // Nothing loads the output, so the current through C2 comes back through R2
+        let i_c2 = (v_out - v_c3) * g2;
+        let i_c1 = i_c2 + v_q * g3;
+        let i_c3 = (u - v_c3) * g1 + i_c2;
+        [i_c1 / c1, i_c2 / c2, i_c3 / c3]
+    }
+
+    fn output(&self, u: f64, x: &[f64; 3]) -> f64 {
+        u - x[0] - x[1]
+    }
+}
+
+impl LinearCircuit<3> for TwinT {
+    fn state_space(&self) -> StateSpace<3> {
+        let [g1, g2, g3] = self.conductances::<f64>();
+        let [c1, c2, c3] = self.capacitors::<f64>();
+        StateSpace {
+            a: [
+                [-(g2 + g3) / c1, -g2 / c1, -g2 / c1],
+                [-g2 / c2, -g2 / c2, -g2 / c2],
+                [-g2 / c3, -g2 / c3, -(g1 + g2) / c3],
+            ],
+            b: [(g2 + g3) / c1, g2 / c2, (g1 + g2) / c3],
+            c: [-1., -1., 0.],
+            d: 1.,
+        }
+    }
+}
+
+/// [`TwinT`] in the feedback path of an inverting op-amp, with the input current coming in
+/// through `r_in` to the inverting input. Its poles are the zeros of the notch, so that a balanced
+/// network rings forever at the notch frequency; raising `R3` damps the ring, and lowering it makes
+/// the ring grow. The op-amp is ideal.
+#[derive(Debug, Clone, Copy)]
+pub struct TwinTResonator {
+    pub twin_t: TwinT,
+    pub r_in: f64,
+}
+
+impl TwinTResonator {
+    pub fn new(twin_t: TwinT, r_in: f64) -> Self {
+        Self { twin_t, r_in }
+    }
+}
+
+impl Lerp for TwinTResonator {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            twin_t: self.twin_t.lerp(&other.twin_t, t),
+            r_in: self.r_in.lerp(&other.r_in, t),
+        }
+    }
+}
+
+/// The state holds the capacitor voltages as in [`TwinT`], where the input of the network is the
+/// output of the op-amp, and its output is the inverting input, held at ground.
+impl OdeSystem<3> for TwinTResonator {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 3]) -> [T; 3] {
+        let [v_c1, v_c2, v_c3] = *x;
+        let [g1, g2, g3] = self.twin_t.conductances::<T>();
+        let [c1, c2, c3] = self.twin_t.capacitors::<T>();
+        let g_in = T::from(self.r_in.recip()).unwrap();
+        let v_out = v_c1 + v_c2;
+        // Whatever comes in through R_in and R2 has to leave the inverting input through C2
+        let i_c2 = -(u * g_in + v_c3 * g2);
+        let i_c1 = i_c2 + v_c2 * g3;
+        let i_c3 = (v_out - v_c3) * g1 - v_c3 * g2;
+        [i_c1 / c1, i_c2 / c2, i_c3 / c3]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 3]) -> f64 {
+        x[0] + x[1]
+    }
+}
+
+impl LinearCircuit<3> for TwinTResonator {
+    fn state_space(&self) -> StateSpace<3> {
+        let [g1, g2, g3] = self.twin_t.conductances::<f64>();
+        let [c1, c2, c3] = self.twin_t.capacitors::<f64>();
+        let g_in = self.r_in.recip();
+        StateSpace {
+            a: [
+                [0., g3 / c1, -g2 / c1],
+                [0., 0., -g2 / c2],
+                [g1 / c3, g1 / c3, -(g1 + g2) / c3],
+            ],
+            b: [-g_in / c1, -g_in / c2, 0.],
+            c: [1., 1., 0.],
+            d: 0.,
+        }
+    }
+}
+
+/// State of the twin-T circuits, which have three capacitors.
+pub type TwinTState<F = TwinT> = CircuitState<F, 3>;
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use approx::{assert_abs_diff_eq, assert_relative_eq};
+    use rustfft::num_complex::Complex;
+
+    use crate::lpf::test_utils::ring;
+    use crate::ode::{Solver, Trapezoidal};
+    use crate::state_space::LinearCircuit;
+
+    use super::{TwinT, TwinTResonator, TwinTState};
+
+    #[test]
+    fn test_twin_t_notch() {
+        let filter = TwinT::balanced(10e3, 10e-9);
+        let fc = filter.fc();
+        let sys = filter.state_space();
+        assert_relative_eq!(sys.dc_gain(), 1., max_relative = 1e-9);
+        assert_relative_eq!(
+            sys.frequency_response(1e3 * fc).norm(),
+            1.,
+            max_relative = 1e-3
+        );
+        assert!(sys.frequency_response(fc).norm() < 1e-9);
+        // The balanced twin-T response is `(1 + s²) / (1 + 4 s + s²)`, with `s` normalized to the
+        // notch frequency
+        for w in [0.25, 4f64] {
+            let expected = (1. - w * w).abs() / f64::hypot(1. - w * w, 4. * w);
+            assert_relative_eq!(
+                sys.frequency_response(w * fc).norm(),
+                expected,
+                max_relative = 1e-9
+            );
+        }
+    }
+
+    /// Pole of the resonator with `R3` scaled by `detune`, in the upper half-plane and normalized
+    /// to the notch frequency. The poles are the zeros of the twin-T, which are the roots of
+    /// `d s³ + d s² + d s + 1` for a detune of `d`.
+    fn pole(detune: f64) -> Complex<f64> {
+        // Find the real root, then factor it out to be left with `s² + p s + q`
+        let mut r = -1.;
+        for _ in 0..20 {
+            r -= (detune * (r * r * r + r * r + r) + 1.) / (detune * (3. * r * r + 2. * r + 1.));
+        }
+        let p = 1. + r;
+        let q = 1. + p * r;
+        Complex::new(-p / 2., (q - p * p / 4.).sqrt())
+    }
+
+    #[test]
+    fn test_twin_t_impulse() {
+        let step = (4. * 44100f64).recip();
+        let fc = TwinT::balanced(10e3, 10e-9).fc();
+        // Frequency and growth rate of the impulse response of the resonator with `R3` scaled by
+        // `detune`, from its peaks
+        let kick = |detune: f64| {
+            let mut twin_t = TwinT::balanced(10e3, 10e-9);
+            twin_t.r3 *= detune;
+            let filter = TwinTResonator::new(twin_t, 100e3);
+            let mut state = TwinTState::new(Solver::Trapezoidal(Trapezoidal::default()));
+            // Skip the first peaks, where the real pole hasn't died out yet
+            let ring = ring(step, 0.05, 1e-6, 2, |x| {
+                state.set_v_in(x);
+                state.process(&filter, step)
+            });
+            assert!(!state.take_diverged());
+            (ring.freq, ring.rate)
+        };
+
+        // Balanced, it rings forever at the notch frequency
+        let (freq, rate) = kick(1.);
+        assert_relative_eq!(freq, fc, max_relative = 1e-3);
+        assert_abs_diff_eq!(rate / (TAU * fc), 0., epsilon = 1e-4);
+
+        // A larger shunt resistor damps the ring, a smaller one makes it grow, both at the rate
+        // given by the real part of the poles
+        for detune in [1.1, 0.9] {
+            let pole = TAU * fc * pole(detune);
+            let (freq, rate) = kick(detune);
+            assert_relative_eq!(freq, pole.im / TAU, max_relative = 1e-3);
+            assert_relative_eq!(rate, pole.re, max_relative = 1e-3);
+        }
+        assert!(pole(1.1).re < 0. && pole(0.9).re > 0.);
+    }
+}
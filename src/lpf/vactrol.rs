@@ -0,0 +1,506 @@
+//! Vactrol, an LED shining on a light-dependent resistor, and the lowpass gate and RC stage it
+//! drives.
+
+use std::f64::consts::TAU;
+
+use num_traits::Float;
+
+use crate::ode::{OdeSystem, Solver};
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::CircuitState;
+
+/// Light-dependent resistor lit by an LED, turning a control signal into a resistance. The
+/// control signal goes from 0, with the LED off, to 1, at full brightness.
+///
+/// The light level reached by the resistor follows the control signal with a time constant of
+/// `attack` when going up, and a much longer one of `release` when going down. The release also
+/// gets slower the longer the resistor has been lit: the light level is averaged over
+/// `memory_time`, and the release time constant grows by `memory` times that average.
+///
+/// The conductance goes up with the light level to the power `gamma`, from `1 / r_off` in the dark
+/// to `1 / r_on` under full light. The defaults are of the order of those of a VTL5C3.
+#[derive(Debug, Clone, Copy)]
+pub struct Vactrol {
+    pub attack: f64,
+    pub release: f64,
+    pub memory: f64,
+    pub memory_time: f64,
+    pub r_on: f64,
+    pub r_off: f64,
+    pub gamma: f64,
+}
+
+impl Default for Vactrol {
+    fn default() -> Self {
+        Self {
+            attack: 12e-3,
+            release: 250e-3,
+            memory: 2.,
+            memory_time: 2.,
+            r_on: 1e3,
+            r_off: 10e6,
+            gamma: 0.7,
+        }
+    }
+}
+
+impl Vactrol {
+    /// Resistance at the light level `light`, between 0 and 1.
+    pub fn resistance(&self, light: f64) -> f64 {
+        let g = self.r_off.recip() + self.r_on.recip() * light.clamp(0., 1.).powf(self.gamma);
+        g.recip()
+    }
+
+    /// Cutoff in Hz of an RC stage made of the resistor at the light level `light` and the
+    /// capacitor `c`.
+    pub fn fc(&self, light: f64, c: f64) -> f64 {
+        (TAU * self.resistance(light) * c).recip()
+    }
+}
+
+/// State of a [`Vactrol`], holding its light level and the average of it over its memory time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VactrolState {
+    light: f64,
+    memory: f64,
+}
+
+impl VactrolState {
+    pub fn light(&self) -> f64 {
+        self.light
+    }
+
+    pub fn reset(&mut self) {
+        self.light = 0.;
+        self.memory = 0.;
+    }
+
+    /// Advance the vactrol by `step` seconds with the control signal held at `cv`, and return the
+    /// resulting resistance. Both time constants are constant over the step, so that it is taken
+    /// exactly.
+    pub fn process(&mut self, vactrol: &Vactrol, cv: f64, step: f64) -> f64 {
+        let target = cv.clamp(0., 1.);
+        let tau = if target > self.light {
+            vactrol.attack
+        } else {
+            vactrol.release * (1. + vactrol.memory * self.memory)
+        };
+        self.light = target + (self.light - target) * (-step / tau).exp();
+        self.memory = self.light + (self.memory - self.light) * (-step / vactrol.memory_time).exp();
+        vactrol.resistance(self.light)
+    }
+}
+
+/// Passive lowpass gate: the resistor of a [`Vactrol`] in series with the input, into the
+/// capacitor `c` loaded by `r_load`. Lighting up the vactrol raises both the cutoff, at
+/// `(1 / R + 1 / r_load) / (2π c)`, and the gain, at `r_load / (R + r_load)`, so that the gate
+/// closes on its own as the resistor goes dark.
+///
+/// The control signal of the vactrol is set with [`Self::set_cv`], and [`LowpassGateState`] runs
+/// the vactrol to update the resistance on every sample.
+#[derive(Debug, Clone, Copy)]
+pub struct LowpassGate {
+    pub vactrol: Vactrol,
+    pub r_load: f64,
+    pub c: f64,
+    cv: f64,
+    r: f64,
+}
+
+impl Default for LowpassGate {
+    fn default() -> Self {
+        let vactrol = Vactrol::default();
+        Self {
+            vactrol,
+            r_load: 100e3,
+            c: 10e-9,
+            cv: 0.,
+            r: vactrol.r_off,
+        }
+    }
+}
+
+impl LowpassGate {
+    /// Set the control signal of the vactrol, between 0 and 1.
+    pub fn set_cv(&mut self, cv: f64) {
+        self.cv = cv;
+    }
+
+    pub fn cv(&self) -> f64 {
+        self.cv
+    }
+
+    /// Set the resistance of the vactrol.
+    pub fn set_resistance(&mut self, r: f64) {
+        self.r = r;
+    }
+
+    pub fn fc(&self) -> f64 {
+        (self.r.recip() + self.r_load.recip()) / (TAU * self.c)
+    }
+
+    pub fn gain(&self) -> f64 {
+        self.r_load / (self.r + self.r_load)
+    }
+}
+
+impl Lerp for LowpassGate {
+    /// Interpolates the conductance of the vactrol rather than its resistance.
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            cv: self.cv.lerp(&other.cv, t),
+            r: self.r.recip().lerp(&other.r.recip(), t).recip(),
+            ..*other
+        }
+    }
+}
+
+/// The state holds the voltage across the capacitor.
+impl OdeSystem<1> for LowpassGate {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 1]) -> [T; 1] {
+        let (g, g_load) = (
+            T::from(self.r.recip()).unwrap(),
+            T::from(self.r_load.recip()).unwrap(),
+        );
+        [((u - x[0]) * g - x[0] * g_load) / T::from(self.c).unwrap()]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 1]) -> f64 {
+        x[0]
+    }
+}
+
+impl LinearCircuit<1> for LowpassGate {
+    fn state_space(&self) -> StateSpace<1> {
+        let (g, g_load) = (self.r.recip(), self.r_load.recip());
+        StateSpace {
+            a: [[-(g + g_load) / self.c]],
+            b: [g / self.c],
+            c: [1.],
+            d: 0.,
+        }
+    }
+}
+
+/// RC stage with the resistor of a [`Vactrol`] in series with the input and the capacitor `c` at
+/// the output. Nothing loads the capacitor, so that unlike [`LowpassGate`], only the cutoff
+/// follows the light level, and the gain stays at unity.
+///
+/// As for the lowpass gate, [`VactrolRcState`] runs the vactrol to update the resistance on every
+/// sample.
+#[derive(Debug, Clone, Copy)]
+pub struct VactrolRc {
+    pub vactrol: Vactrol,
+    pub c: f64,
+    cv: f64,
+    r: f64,
+}
+
+impl Default for VactrolRc {
+    fn default() -> Self {
+        let vactrol = Vactrol::default();
+        Self {
+            vactrol,
+            c: 10e-9,
+            cv: 0.,
+            r: vactrol.r_off,
+        }
+    }
+}
+
+impl VactrolRc {
+    /// Set the control signal of the vactrol, between 0 and 1.
+    pub fn set_cv(&mut self, cv: f64) {
+        self.cv = cv;
+    }
+
+    pub fn cv(&self) -> f64 {
+        self.cv
+    }
+
+    /// Set the resistance of the vactrol.
+    pub fn set_resistance(&mut self, r: f64) {
+        self.r = r;
+    }
+
+    /// Cutoff in Hz at the light level `light`.
+    pub fn fc(&self, light: f64) -> f64 {
+        self.vactrol.fc(light, self.c)
+    }
+}
+
+impl Lerp for VactrolRc {
+    /// Interpolates the conductance of the vactrol rather than its resistance.
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            cv: self.cv.lerp(&other.cv, t),
+            r: self.r.recip().lerp(&other.r.recip(), t).recip(),
+            ..*other
+        }
+    }
+}
+
+/// The state holds the voltage across the capacitor.
+impl OdeSystem<1> for VactrolRc {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 1]) -> [T; 1] {
+        [(u - x[0]) / T::from(self.r * self.c).unwrap()]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 1]) -> f64 {
+        x[0]
+    }
+}
+
+impl LinearCircuit<1> for VactrolRc {
+    fn state_space(&self) -> StateSpace<1> {
+        let wc = (self.r * self.c).recip();
+        StateSpace {
+            a: [[-wc]],
+            b: [wc],
+            c: [1.],
+            d: 0.,
+        }
+    }
+}
+
+/// Circuit with the resistor of a [`Vactrol`] in it, driven by a control signal.
+pub trait VactrolCircuit {
+    fn vactrol(&self) -> &Vactrol;
+
+    fn cv(&self) -> f64;
+
+    fn set_resistance(&mut self, r: f64);
+}
+
+impl VactrolCircuit for LowpassGate {
+    fn vactrol(&self) -> &Vactrol {
+        &self.vactrol
+    }
+
+    fn cv(&self) -> f64 {
+        self.cv
+    }
+
+    fn set_resistance(&mut self, r: f64) {
+        self.r = r;
+    }
+}
+
+impl VactrolCircuit for VactrolRc {
+    fn vactrol(&self) -> &Vactrol {
+        &self.vactrol
+    }
+
+    fn cv(&self) -> f64 {
+        self.cv
+    }
+
+    fn set_resistance(&mut self, r: f64) {
+        self.r = r;
+    }
+}
+
+/// State of a circuit driven by a vactrol, holding the state of the circuit next to that of the
+/// vactrol.
+#[derive(Debug, Clone, Copy)]
+pub struct VactrolCircuitState<F, const N: usize> {
+    circuit: CircuitState<F, N>,
+    vactrol: VactrolState,
+}
+
+impl<F, const N: usize> Default for VactrolCircuitState<F, N> {
+    fn default() -> Self {
+        Self::new(Solver::default())
+    }
+}
+
+impl<F, const N: usize> VactrolCircuitState<F, N> {
+    pub fn new(solver: Solver<N>) -> Self {
+        Self {
+            circuit: CircuitState::new(solver),
+            vactrol: VactrolState::default(),
+        }
+    }
+
+    pub fn set_v_in(&mut self, v_in: f64) {
+        self.circuit.set_v_in(v_in);
+    }
+
+    pub fn set_solver(&mut self, solver: Solver<N>) {
+        self.circuit.set_solver(solver);
+    }
+
+    /// Set the number of solver steps taken per call to [`Self::process`].
+    pub fn set_substeps(&mut self, substeps: usize) {
+        self.circuit.set_substeps(substeps);
+    }
+
+    pub fn substeps(&self) -> usize {
+        self.circuit.substeps()
+    }
+
+    /// Light level of the vactrol, between 0 and 1.
+    pub fn light(&self) -> f64 {
+        self.vactrol.light()
+    }
+
+    /// Clears the circuit and puts the vactrol back in the dark.
+    pub fn reset(&mut self) {
+        self.circuit.reset();
+        self.vactrol.reset();
+    }
+
+    /// Whether the simulation diverged and was reset since the last call to this method.
+    pub fn take_diverged(&mut self) -> bool {
+        self.circuit.take_diverged()
+    }
+}
+
+impl<F: VactrolCircuit + OdeSystem<N> + Lerp + Copy, const N: usize> VactrolCircuitState<F, N> {
+    /// Advance the vactrol by `step` seconds and process one sample through `filter` with the
+    /// resistance it reached.
+    pub fn process(&mut self, filter: &F, step: f64) -> f64 {
+        let mut filter = *filter;
+        filter.set_resistance(self.vactrol.process(filter.vactrol(), filter.cv(), step));
+        self.circuit.process(&filter, step)
+    }
+}
+
+/// State of the lowpass gate, holding the voltage across its capacitor.
+pub type LowpassGateState = VactrolCircuitState<LowpassGate, 1>;
+
+/// State of the vactrol RC stage, holding the voltage across its capacitor.
+pub type VactrolRcState = VactrolCircuitState<VactrolRc, 1>;
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    use approx::assert_relative_eq;
+
+    use crate::lpf::test_utils::measure_gain;
+    use crate::ode::{Exponential, Solver};
+    use crate::state_space::LinearCircuit;
+
+    use super::{LowpassGate, LowpassGateState, Vactrol, VactrolRc, VactrolRcState, VactrolState};
+
+    const STEP: f64 = 1. / (4. * 44100.);
+
+    /// Time taken for the resistance of the vactrol to cross `r` with the control signal held at
+    /// `cv`.
+    fn time_to(vactrol: &Vactrol, state: &mut VactrolState, cv: f64, r: f64) -> f64 {
+        let above = vactrol.resistance(state.light()) > r;
+        let mut n = 0;
+        while (state.process(vactrol, cv, STEP) > r) == above {
+            n += 1;
+        }
+        n as f64 * STEP
+    }
+
+    #[test]
+    fn test_vactrol_dynamics() {
+        let vactrol = Vactrol::default();
+        assert_relative_eq!(vactrol.resistance(0.), vactrol.r_off);
+        assert_relative_eq!(
+            vactrol.resistance(1.),
+            (vactrol.r_on.recip() + vactrol.r_off.recip()).recip()
+        );
+
+        // Lighting up is much faster than going dark
+        let mut state = VactrolState::default();
+        let half = vactrol.resistance(0.5);
+        let attack = time_to(&vactrol, &mut state, 1., half);
+        assert_relative_eq!(attack, vactrol.attack * 2f64.ln(), max_relative = 1e-3);
+        for _ in 0..(0.1 / STEP) as usize {
+            state.process(&vactrol, 1., STEP);
+        }
+        let release = time_to(&vactrol, &mut state, 0., half);
+        assert!(release > 10. * attack);
+
+        // After being lit for a long time, the resistor takes longer to go dark
+        let release_after = |lit: f64| {
+            let mut state = VactrolState::default();
+            for _ in 0..(lit / STEP) as usize {
+                state.process(&vactrol, 1., STEP);
+            }
+            time_to(&vactrol, &mut state, 0., 100e3)
+        };
+        assert!(release_after(5.) > 1.5 * release_after(0.1));
+    }
+
+    #[test]
+    fn test_lowpass_gate() {
+        let mut filter = LowpassGate::default();
+        filter.set_resistance(10e3);
+        let sys = filter.state_space();
+        assert_relative_eq!(sys.dc_gain(), filter.gain(), max_relative = 1e-9);
+        assert_relative_eq!(
+            sys.frequency_response(filter.fc()).norm(),
+            filter.gain() * FRAC_1_SQRT_2,
+            max_relative = 1e-9
+        );
+
+        // The gate opens with the vactrol, and closes after it goes dark
+        let mut state = LowpassGateState::new(Solver::Exponential(Exponential::default()));
+        let mut run = |filter: &LowpassGate, seconds: f64| {
+            let mut out = 0.;
+            for _ in 0..(seconds / STEP) as usize {
+                state.set_v_in(1.);
+                out = state.process(filter, STEP);
+            }
+            out
+        };
+        assert!(run(&filter, 0.1) < 2e-2);
+        filter.set_cv(1.);
+        let open = run(&filter, 0.1);
+        assert_relative_eq!(
+            open,
+            filter.r_load / (filter.vactrol.resistance(1.) + filter.r_load),
+            max_relative = 1e-3
+        );
+        filter.set_cv(0.);
+        assert!(run(&filter, 0.1) > 0.5 * open);
+        assert!(run(&filter, 5.) < 2e-2);
+        assert!(!state.take_diverged());
+    }
+
+    #[test]
+    fn test_vactrol_rc() {
+        let mut filter = VactrolRc {
+            c: 100e-9,
+            ..VactrolRc::default()
+        };
+        let mut state = VactrolRcState::new(Solver::Exponential(Exponential::default()));
+        let settle = |state: &mut VactrolRcState, filter: &VactrolRc, seconds: f64| {
+            for _ in 0..(seconds / STEP) as usize {
+                state.set_v_in(0.);
+                state.process(filter, STEP);
+            }
+        };
+        let fc = filter.fc(0.5);
+        let gain = |state: &mut VactrolRcState, filter: &VactrolRc| {
+            measure_gain(fc, STEP, |x| {
+                state.set_v_in(x);
+                state.process(filter, STEP)
+            })
+        };
+
+        // Once the vactrol has settled, the stage is 3 dB down at the cutoff of its resistance
+        filter.set_cv(0.5);
+        settle(&mut state, &filter, 0.2);
+        assert_relative_eq!(state.light(), 0.5, max_relative = 1e-6);
+        assert_relative_eq!(
+            gain(&mut state, &filter),
+            FRAC_1_SQRT_2,
+            max_relative = 1e-2
+        );
+
+        // As it goes dark, the cutoff falls far below
+        filter.set_cv(0.);
+        settle(&mut state, &filter, 5.);
+        assert!(gain(&mut state, &filter) < 1e-2);
+        assert!(!state.take_diverged());
+    }
+}
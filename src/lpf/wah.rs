@@ -0,0 +1,229 @@
+//! Inductor wah, after the Dunlop Cry Baby.
+
+use std::f64::consts::TAU;
+
+use num_traits::Float;
+
+use crate::ode::OdeSystem;
+use crate::state_space::{LinearCircuit, StateSpace};
+use crate::utils::Lerp;
+
+use super::tone_stack::Taper;
+use super::CircuitState;
+
+/// State of the wah, holding its capacitor voltage and inductor flux.
+pub type WahState<F = Wah> = CircuitState<F, 2>;
+
+/// Resonant bandpass made of an inductor `l` to ground at the input of a transistor follower of
+/// gain `gain`, fed through `r_in` and damped by `r_q`. The capacitor `c` goes from that node to
+/// the wiper of the pedal pot, which taps the follower output: as the pedal goes from heel to toe,
+/// the follower bootstraps the capacitor more and more, which shrinks its effective value and
+/// sweeps the resonance up from about 480 Hz to 2.4 kHz.
+///
+/// With `ρ` the pot fraction and `k = 1 - ρ gain`, the resonance is at `1 / (2π √(l c k))`, with a
+/// Q of `(r_in ‖ r_q) √(k c / l)`.
+///
+/// The inductor core optionally saturates, with a current growing as the `sinh` of its flux, so
+/// that large signals see a smaller inductance.
+#[derive(Debug, Clone, Copy)]
+pub struct Wah {
+    pedal: f64,
+    pub taper: Taper,
+    pub l: f64,
+    pub c: f64,
+    pub r_in: f64,
+    pub r_q: f64,
+    pub gain: f64,
+    /// Current at which the inductor core saturates, or `None` for an ideal inductor.
+    pub saturation: Option<f64>,
+}
+
+impl Default for Wah {
+    fn default() -> Self {
+        Self {
+            pedal: 0.,
+            taper: Taper::Linear,
+            l: 0.5,
+            c: 220e-9,
+            r_in: 68e3,
+            r_q: 33e3,
+            gain: 0.96,
+            saturation: None,
+        }
+    }
+}
+
+impl Wah {
+    /// Set the pedal position, from heel at 0 to toe at 1.
+    pub fn set_pedal(&mut self, pedal: f64) {
+        self.pedal = pedal;
+    }
+
+    pub fn set_saturation(&mut self, saturation: Option<f64>) {
+        self.saturation = saturation;
+    }
+
+    /// Fraction of the capacitor voltage swing left over by the bootstrapping.
+    fn k(&self) -> f64 {
+        1. - self.taper.fraction(self.pedal) * self.gain
+    }
+
+    /// Small-signal center frequency.
+    pub fn fc(&self) -> f64 {
+        (TAU * (self.l * self.c * self.k()).sqrt()).recip()
+    }
+
+    /// Small-signal Q.
+    pub fn q(&self) -> f64 {
+        let r = (self.r_in.recip() + self.r_q.recip()).recip();
+        r * (self.k() * self.c / self.l).sqrt()
+    }
+
+    fn inductor_current<T: Float>(&self, flux: T) -> T {
+        let l = T::from(self.l).unwrap();
+        match self.saturation {
+            None => flux / l,
+            Some(i_sat) => {
+                let i_sat = T::from(i_sat).unwrap();
+                i_sat * (flux / (l * i_sat)).sinh()
+            }
+        }
+    }
+}
+
+impl Lerp for Wah {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            pedal: self.pedal.lerp(&other.pedal, t),
+            ..*other
+        }
+    }
+}
+
+/// The state holds the voltage across the capacitor, and the flux through the inductor.
+impl OdeSystem<2> for Wah {
+    fn derivative<T: Float>(&self, u: T, x: &[T; 2]) -> [T; 2] {
+        let [v_c, flux] = *x;
+        let v_x = v_c / T::from(self.k()).unwrap();
+        let (g_in, g_q) = (
+            T::from(self.r_in.recip()).unwrap(),
+            T::from(self.r_q.recip()).unwrap(),
+        );
+        let i_c = (u - v_x) * g_in - v_x * g_q - self.inductor_current(flux);
+        [i_c / T::from(self.c).unwrap(), v_x]
+    }
+
+    fn output(&self, _u: f64, x: &[f64; 2]) -> f64 {
+        self.gain * x[0] / self.k()
+    }
+}
+
+/// Small-signal model, where the inductor doesn't saturate.
+impl LinearCircuit<2> for Wah {
+    fn state_space(&self) -> StateSpace<2> {
+        let (k, c) = (self.k(), self.c);
+        let (g_in, g_q) = (self.r_in.recip(), self.r_q.recip());
+        StateSpace {
+            a: [
+                [-(g_in + g_q) / (k * c), -(self.l * c).recip()],
+                [k.recip(), 0.],
+            ],
+            b: [g_in / c, 0.],
+            c: [self.gain / k, 0.],
+            d: 0.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use approx::assert_relative_eq;
+
+    use crate::lpf::test_utils::measure_gain;
+    use crate::ode::{Solver, Trapezoidal};
+    use crate::state_space::LinearCircuit;
+
+    use super::{Wah, WahState};
+
+    #[test]
+    fn test_wah_sweep() {
+        let step = (4. * 44100f64).recip();
+        let mut filter = Wah::default();
+        let r = (filter.r_in.recip() + filter.r_q.recip()).recip();
+        let peak = filter.gain * r / filter.r_in;
+        let mut last_fc = 0.;
+        for pedal in [0., 0.5, 1.] {
+            filter.set_pedal(pedal);
+            let (fc, q) = (filter.fc(), filter.q());
+            assert!(fc > last_fc);
+            last_fc = fc;
+            let sys = filter.state_space();
+            assert_relative_eq!(sys.frequency_response(fc).norm(), peak, max_relative = 1e-9);
+            // Bandpass response around the center frequency
+            for w in [0.5, 2f64] {
+                let expected = peak / f64::hypot(1., q * (w - w.recip()));
+                assert_relative_eq!(
+                    sys.frequency_response(w * fc).norm(),
+                    expected,
+                    max_relative = 1e-9
+                );
+            }
+            let mut state = WahState::new(Solver::Trapezoidal(Trapezoidal::default()));
+            let gain = measure_gain(fc, step, |x| {
+                state.set_v_in(1e-2 * x);
+                state.process(&filter, step) / 1e-2
+            });
+            assert_relative_eq!(gain, peak, max_relative = 1e-2);
+        }
+        // From about 480 Hz at the heel to 2.37 kHz at the toe
+        filter.set_pedal(0.);
+        assert_relative_eq!(filter.fc(), 480., max_relative = 1e-2);
+        filter.set_pedal(1.);
+        assert_relative_eq!(filter.fc(), 2.37e3, max_relative = 1e-2);
+    }
+
+    #[test]
+    fn test_wah_saturation() {
+        let step = (4. * 44100f64).recip();
+        let linear = Wah::default();
+        let mut saturating = linear;
+        saturating.set_saturation(Some(1e-3));
+        let fc = linear.fc();
+        // Gain at the resonance of the linear and the saturating wah, for a small and a large sine
+        let [small, large] = [1e-3, 10.].map(|amplitude| {
+            [linear, saturating].map(|filter| {
+                let mut state = WahState::new(Solver::Trapezoidal(Trapezoidal::default()));
+                measure_gain(fc, step, |x| {
+                    state.set_v_in(amplitude * x);
+                    state.process(&filter, step) / amplitude
+                })
+            })
+        });
+        // Small signals stay well below the saturation current
+        assert_relative_eq!(small[1], small[0], max_relative = 1e-3);
+        // Large ones see a smaller inductance, which detunes the resonance away from the input
+        assert!(large[1] < 0.8 * large[0]);
+    }
+
+    #[test]
+    fn test_wah_pedal_automation() {
+        // Sweeping the pedal on every sample keeps the output bounded and continuous
+        let step = (4. * 44100f64).recip();
+        let mut filter = Wah::default();
+        let mut state = WahState::new(Solver::Trapezoidal(Trapezoidal::default()));
+        let len = (0.2 / step) as usize;
+        let mut last = 0f64;
+        for n in 0..len {
+            let t = n as f64 * step;
+            filter.set_pedal(0.5 - 0.5 * f64::cos(TAU * 5. * t));
+            state.set_v_in(f64::sin(TAU * 300. * t));
+            let out = state.process(&filter, step);
+            assert!(out.abs() < 2.);
+            assert!((out - last).abs() < 0.1);
+            last = out;
+        }
+        assert!(!state.take_diverged());
+    }
+}
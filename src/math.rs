@@ -1,11 +1,14 @@
 #![allow(dead_code)]
 use std::f64::consts::TAU;
 
-use num_traits::Float;
+use num_traits::{Float, NumAssign};
 use rustfft::{num_complex::Complex, FftNum, FftPlanner};
 
 use crate::utils::{normalize, zeros};
 
+/// Dense square matrix, stored row-major.
+pub type Matrix<const N: usize> = [[f64; N]; N];
+
 pub fn sinc<T: Float>(x: T) -> T {
     if x.is_zero() {
         T::one()
@@ -74,3 +77,136 @@ pub fn ifft<T: FftNum>(mut data: Vec<Complex<T>>) -> Vec<T> {
     fft.process(&mut data);
     data.into_iter().map(|c| c.re).collect()
 }
+
+/// Solve `a * x = b` by Gaussian elimination with partial pivoting. Returns `None` when `a` is
+/// singular.
+///
+/// Works for both real and complex systems, the latter being used for frequency responses.
+pub fn solve_linear<T, const N: usize>(mut a: [[T; N]; N], mut b: [T; N]) -> Option<[T; N]>
+where
+    T: Copy + NumAssign + Into<Complex<f64>>,
+{
+    let norm = |x: T| Into::<Complex<f64>>::into(x).norm();
+    for col in 0..N {
+        let pivot = (col..N).max_by(|&i, &j| norm(a[i][col]).total_cmp(&norm(a[j][col])))?;
+        if norm(a[pivot][col]) < f64::EPSILON {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..N {
+            let fac = a[row][col] / a[col][col];
+            for k in col..N {
+                let v = a[col][k];
+                a[row][k] -= fac * v;
+            }
+            let v = b[col];
+            b[row] -= fac * v;
+        }
+    }
+
+    let mut x = [T::zero(); N];
+    for row in (0..N).rev() {
+        let sum = (row + 1..N).fold(T::zero(), |acc, k| acc + a[row][k] * x[k]);
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Inverse of the matrix, or `None` if it is singular.
+pub fn inverse<const N: usize>(a: &Matrix<N>) -> Option<Matrix<N>> {
+    let id = identity::<N>();
+    let mut inv = [[0.; N]; N];
+    for j in 0..N {
+        let col = solve_linear(*a, std::array::from_fn(|i| id[i][j]))?;
+        for i in 0..N {
+            inv[i][j] = col[i];
+        }
+    }
+    Some(inv)
+}
+
+pub fn identity<const N: usize>() -> Matrix<N> {
+    std::array::from_fn(|i| std::array::from_fn(|j| if i == j { 1. } else { 0. }))
+}
+
+pub fn mat_mul<const N: usize>(a: &Matrix<N>, b: &Matrix<N>) -> Matrix<N> {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..N).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+pub fn mat_vec<const N: usize>(a: &Matrix<N>, x: &[f64; N]) -> [f64; N] {
+    std::array::from_fn(|i| (0..N).map(|k| a[i][k] * x[k]).sum())
+}
+
+/// Compute the matrix exponential `exp(a h)` along with its integral `∫₀ʰ exp(a s) ds`, by scaling
+/// and squaring of their Taylor series.
+///
+/// Together they give the exact (zero-order hold) update of the linear system `x' = a x + b u`:
+/// `x(t + h) = exp(a h) x(t) + ∫₀ʰ exp(a s) ds b u`.
+pub fn expm_integral<const N: usize>(a: &Matrix<N>, h: f64) -> (Matrix<N>, Matrix<N>) {
+    let norm = a
+        .iter()
+        .map(|row| row.iter().map(|x| x.abs()).sum::<f64>())
+        .fold(0., f64::max)
+        * h.abs();
+    let squarings = if norm > 0.5 {
+        (norm / 0.5).log2().ceil() as i32
+    } else {
+        0
+    };
+    let tau = h / 2f64.powi(squarings);
+
+    let mut exp = identity();
+    let mut int = identity::<N>().map(|row| row.map(|x| x * tau));
+    let mut term = identity();
+    let a_tau = a.map(|row| row.map(|x| x * tau));
+    for k in 1..=16 {
+        term = mat_mul(&term, &a_tau).map(|row| row.map(|x| x / k as f64));
+        for i in 0..N {
+            for j in 0..N {
+                exp[i][j] += term[i][j];
+                int[i][j] += tau * term[i][j] / (k + 1) as f64;
+            }
+        }
+    }
+
+    for _ in 0..squarings {
+        let exp_int = mat_mul(&exp, &int);
+        for i in 0..N {
+            for j in 0..N {
+                int[i][j] += exp_int[i][j];
+            }
+        }
+        exp = mat_mul(&exp, &exp);
+    }
+    (exp, int)
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::{expm_integral, solve_linear};
+
+    #[test]
+    fn test_solve_linear() {
+        let a = [[2., 1., -1.], [-3., -1., 2.], [-2., 1., 2.]];
+        let x = solve_linear(a, [8., -11., -3.]).unwrap();
+        assert_abs_diff_eq!(x[0], 2., epsilon = 1e-12);
+        assert_abs_diff_eq!(x[1], 3., epsilon = 1e-12);
+        assert_abs_diff_eq!(x[2], -1., epsilon = 1e-12);
+        assert!(solve_linear([[1., 2.], [2., 4.]], [1., 1.]).is_none());
+    }
+
+    #[test]
+    fn test_expm_rotation() {
+        // exp of the rotation generator over an angle t
+        let t = 10.;
+        let (exp, int) = expm_integral(&[[0., -1.], [1., 0.]], t);
+        assert_abs_diff_eq!(exp[0][0], t.cos(), epsilon = 1e-12);
+        assert_abs_diff_eq!(exp[0][1], -t.sin(), epsilon = 1e-12);
+        assert_abs_diff_eq!(exp[1][0], t.sin(), epsilon = 1e-12);
+        assert_abs_diff_eq!(int[0][0], t.sin(), epsilon = 1e-12);
+        assert_abs_diff_eq!(int[1][0], 1. - t.cos(), epsilon = 1e-12);
+    }
+}
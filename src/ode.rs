@@ -0,0 +1,701 @@
+#![allow(dead_code)]
+//! Numerical integration of the circuit models.
+//!
+//! Circuits are described as first-order ODE systems `x' = f(x, u)` through [`OdeSystem`], and
+//! are advanced in time by any [`Integrator`]. [`Solver`] wraps the available integrators so that
+//! the method can be chosen at runtime when constructing a filter state.
+
+use num_traits::Float;
+
+use crate::dual::Dual;
+use crate::math::{expm_integral, mat_vec, solve_linear, Matrix};
+use crate::utils::Lerp;
+
+/// Magnitude, in volts, above which a state variable is considered to have diverged.
+pub const DIVERGENCE_LIMIT: f64 = 1e6;
+
+/// Whether any of the state variables is non-finite or has grown beyond [`DIVERGENCE_LIMIT`].
+pub fn is_diverged<const N: usize>(x: &[f64; N]) -> bool {
+    x.iter()
+        .any(|v| !v.is_finite() || v.abs() > DIVERGENCE_LIMIT)
+}
+
+/// A continuous-time circuit model with `N` state variables (usually capacitor voltages), driven
+/// by the input voltage `u`.
+pub trait OdeSystem<const N: usize> {
+    /// Time derivative of the state `x`.
+    ///
+    /// This is generic over the number type so that the Jacobian can be computed automatically
+    /// with [`Dual`] numbers.
+    fn derivative<T: Float>(&self, u: T, x: &[T; N]) -> [T; N];
+
+    /// Output voltage of the circuit for the given state.
+    fn output(&self, u: f64, x: &[f64; N]) -> f64;
+
+    /// Jacobian of [`Self::derivative`] with respect to the state, used by the implicit solvers.
+    ///
+    /// The default implementation differentiates [`Self::derivative`] automatically; override it
+    /// when the analytical expression is cheaper to compute.
+    fn jacobian(&self, u: f64, x: &[f64; N]) -> Matrix<N> {
+        let xd = std::array::from_fn(|i| Dual::variable(x[i], i));
+        let f = self.derivative(Dual::constant(u), &xd);
+        std::array::from_fn(|i| f[i].eps)
+    }
+}
+
+/// Jacobian of the system by forward finite differences, as a reference for
+/// [`OdeSystem::jacobian`].
+pub fn finite_difference_jacobian<S: OdeSystem<N>, const N: usize>(
+    system: &S,
+    u: f64,
+    x: &[f64; N],
+) -> Matrix<N> {
+    let f0 = system.derivative(u, x);
+    let mut jac = [[0.; N]; N];
+    for j in 0..N {
+        let dx = f64::EPSILON.sqrt() * x[j].abs().max(1.);
+        let mut xp = *x;
+        xp[j] += dx;
+        let fj = system.derivative(u, &xp);
+        for i in 0..N {
+            jac[i][j] = (fj[i] - f0[i]) / dx;
+        }
+    }
+    jac
+}
+
+/// A method advancing an [`OdeSystem`] by one time step.
+pub trait Integrator<const N: usize> {
+    /// Advance the state `x` by `h` seconds, holding the input `u` constant over the step.
+    fn step<S: OdeSystem<N>>(&mut self, system: &S, u: f64, x: &mut [f64; N], h: f64);
+
+    /// Forget any history kept from previous steps.
+    fn reset(&mut self) {}
+}
+
+/// Forward Euler: first order, cheap, and only stable for steps well below the smallest time
+/// constant of the system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Euler;
+
+impl<const N: usize> Integrator<N> for Euler {
+    fn step<S: OdeSystem<N>>(&mut self, system: &S, u: f64, x: &mut [f64; N], h: f64) {
+        let k1 = system.derivative(u, x);
+        *x = add_scaled(x, h, &k1);
+    }
+}
+
+/// Heun's method (explicit trapezoidal), second order.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Heun;
+
+impl<const N: usize> Integrator<N> for Heun {
+    fn step<S: OdeSystem<N>>(&mut self, system: &S, u: f64, x: &mut [f64; N], h: f64) {
+        let k1 = system.derivative(u, x);
+        let k2 = system.derivative(u, &add_scaled(x, h, &k1));
+        *x = std::array::from_fn(|i| x[i] + 0.5 * h * (k1[i] + k2[i]));
+    }
+}
+
+/// Classic fourth-order Runge-Kutta.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rk4;
+
+impl<const N: usize> Integrator<N> for Rk4 {
+    fn step<S: OdeSystem<N>>(&mut self, system: &S, u: f64, x: &mut [f64; N], h: f64) {
+        let k1 = system.derivative(u, x);
+        let k2 = system.derivative(u, &add_scaled(x, 0.5 * h, &k1));
+        let k3 = system.derivative(u, &add_scaled(x, 0.5 * h, &k2));
+        let k4 = system.derivative(u, &add_scaled(x, h, &k3));
+        *x = std::array::from_fn(|i| x[i] + h / 6. * (k1[i] + 2. * k2[i] + 2. * k3[i] + k4[i]));
+    }
+}
+
+/// Implicit trapezoidal rule (the bilinear transform of the circuit), A-stable and second order.
+///
+/// The implicit equation is solved with Newton-Raphson, with at most `max_iterations` iterations
+/// per step so that the cost stays bounded on the audio thread.
+#[derive(Debug, Clone, Copy)]
+pub struct Trapezoidal {
+    pub max_iterations: usize,
+    pub tolerance: f64,
+}
+
+impl Default for Trapezoidal {
+    fn default() -> Self {
+        Self {
+            max_iterations: 8,
+            tolerance: 1e-9,
+        }
+    }
+}
+
+impl<const N: usize> Integrator<N> for Trapezoidal {
+    fn step<S: OdeSystem<N>>(&mut self, system: &S, u: f64, x: &mut [f64; N], h: f64) {
+        let f0 = system.derivative(u, x);
+        let mut next = *x;
+        for _ in 0..self.max_iterations {
+            // Residual g(y) = y - x - h/2 (f(x) + f(y)), with Jacobian I - h/2 J_f(y)
+            let f1 = system.derivative(u, &next);
+            let residual: [f64; N] =
+                std::array::from_fn(|i| next[i] - x[i] - 0.5 * h * (f0[i] + f1[i]));
+            let mut jac = system.jacobian(u, &next);
+            for i in 0..N {
+                for j in 0..N {
+                    jac[i][j] *= -0.5 * h;
+                }
+                jac[i][i] += 1.;
+            }
+            let delta = match solve_linear(jac, residual) {
+                Some(delta) => delta,
+                None => break,
+            };
+            let mut err = 0f64;
+            for i in 0..N {
+                next[i] -= delta[i];
+                err = err.max(delta[i].abs());
+            }
+            if err < self.tolerance {
+                break;
+            }
+        }
+        *x = next;
+    }
+}
+
+/// Embedded Runge-Kutta 5(4) pair of Dormand and Prince, with adaptive step size.
+///
+/// Each call to [`Integrator::step`] is split into as many substeps as needed to keep the local
+/// error estimate within tolerance. This is meant for offline rendering, where accuracy matters
+/// more than a predictable CPU cost.
+#[derive(Debug, Clone, Copy)]
+pub struct DormandPrince {
+    pub rtol: f64,
+    pub atol: f64,
+    /// Substeps after which the rest of the step is taken at once, whatever the error.
+    pub max_substeps: usize,
+    /// Substep size proposed by the error controller, carried over to the next step.
+    dt: f64,
+    substeps: usize,
+}
+
+impl Default for DormandPrince {
+    fn default() -> Self {
+        Self::new(1e-6, 1e-9)
+    }
+}
+
+impl DormandPrince {
+    const C: [f64; 7] = [0., 1. / 5., 3. / 10., 4. / 5., 8. / 9., 1., 1.];
+    const A: [[f64; 6]; 7] = [
+        [0.; 6],
+        [1. / 5., 0., 0., 0., 0., 0.],
+        [3. / 40., 9. / 40., 0., 0., 0., 0.],
+        [44. / 45., -56. / 15., 32. / 9., 0., 0., 0.],
+        [
+            19372. / 6561.,
+            -25360. / 2187.,
+            64448. / 6561.,
+            -212. / 729.,
+            0.,
+            0.,
+        ],
+        [
+            9017. / 3168.,
+            -355. / 33.,
+            46732. / 5247.,
+            49. / 176.,
+            -5103. / 18656.,
+            0.,
+        ],
+        [
+            35. / 384.,
+            0.,
+            500. / 1113.,
+            125. / 192.,
+            -2187. / 6784.,
+            11. / 84.,
+        ],
+    ];
+    /// Difference between the 5th and 4th order weights, giving the local error estimate.
+    const E: [f64; 7] = [
+        71. / 57600.,
+        0.,
+        -71. / 16695.,
+        71. / 1920.,
+        -17253. / 339200.,
+        22. / 525.,
+        -1. / 40.,
+    ];
+
+    pub fn new(rtol: f64, atol: f64) -> Self {
+        Self {
+            rtol,
+            atol,
+            max_substeps: 1000,
+            dt: 0.,
+            substeps: 0,
+        }
+    }
+
+    /// Number of substeps taken during the last call to [`Integrator::step`].
+    pub fn substeps(&self) -> usize {
+        self.substeps
+    }
+
+    /// Attempt a single substep of length `dt`, returning the new state and the normalized error.
+    fn try_substep<S: OdeSystem<N>, const N: usize>(
+        &self,
+        system: &S,
+        u: f64,
+        x: &[f64; N],
+        dt: f64,
+    ) -> ([f64; N], f64) {
+        let mut k = [[0.; N]; 7];
+        for stage in 0..7 {
+            let xs = std::array::from_fn(|i| {
+                x[i] + dt * (0..stage).map(|j| Self::A[stage][j] * k[j][i]).sum::<f64>()
+            });
+            k[stage] = system.derivative(u, &xs);
+        }
+        // The last stage is evaluated at the 5th order solution (first same as last)
+        let next = std::array::from_fn(|i| {
+            x[i] + dt * (0..6).map(|j| Self::A[6][j] * k[j][i]).sum::<f64>()
+        });
+        let mut err = 0f64;
+        for i in 0..N {
+            let e = dt * (0..7).map(|j| Self::E[j] * k[j][i]).sum::<f64>();
+            let scale = self.atol + self.rtol * x[i].abs().max(next[i].abs());
+            err = err.max(e.abs() / scale);
+        }
+        (next, err)
+    }
+}
+
+impl<const N: usize> Integrator<N> for DormandPrince {
+    fn step<S: OdeSystem<N>>(&mut self, system: &S, u: f64, x: &mut [f64; N], h: f64) {
+        let mut t = 0.;
+        let mut dt = if self.dt > 0. { self.dt.min(h) } else { h };
+        self.substeps = 0;
+        while t < h {
+            if self.substeps + 1 >= self.max_substeps {
+                // Out of budget: take whatever is left of the step at once, whatever the error
+                (*x, _) = self.try_substep(system, u, x, h - t);
+                self.substeps += 1;
+                break;
+            }
+            let this_dt = dt.min(h - t);
+            let (next, err) = self.try_substep(system, u, x, this_dt);
+            let factor = (0.9 * err.powf(-0.2)).clamp(0.2, 5.);
+            if err <= 1. {
+                *x = next;
+                t += this_dt;
+                self.substeps += 1;
+                // Don't let the truncated last substep shrink the proposal for the next sample
+                if this_dt == dt {
+                    dt *= factor;
+                }
+            } else if err.is_finite() {
+                dt = this_dt * factor;
+            } else {
+                dt = this_dt * 0.2;
+            }
+        }
+        self.dt = dt;
+    }
+
+    fn reset(&mut self) {
+        self.dt = 0.;
+        self.substeps = 0;
+    }
+}
+
+/// Second-order backward differentiation formula (Gear's method), L-stable, for stiff circuits
+/// whose time constants are widely separated.
+///
+/// The first step after a reset or a change of step size is taken with backward Euler, as BDF2
+/// needs the state from the previous step. The Jacobian is kept across steps and only
+/// re-evaluated when Newton-Raphson stops converging quickly with it.
+#[derive(Debug, Clone, Copy)]
+pub struct Bdf2<const N: usize> {
+    pub max_iterations: usize,
+    pub tolerance: f64,
+    prev: Option<([f64; N], f64)>,
+    jac: Option<Matrix<N>>,
+}
+
+impl<const N: usize> Default for Bdf2<N> {
+    fn default() -> Self {
+        Self {
+            max_iterations: 8,
+            tolerance: 1e-9,
+            prev: None,
+            jac: None,
+        }
+    }
+}
+
+impl<const N: usize> Bdf2<N> {
+    /// Solve `y = rhs + beta_h * f(y)` with Newton-Raphson starting from `guess`.
+    fn solve<S: OdeSystem<N>>(
+        &mut self,
+        system: &S,
+        u: f64,
+        rhs: &[f64; N],
+        beta_h: f64,
+        guess: [f64; N],
+    ) -> [f64; N] {
+        let mut y = guess;
+        let (mut jac, mut fresh) = match self.jac {
+            Some(jac) => (jac, false),
+            None => (system.jacobian(u, &y), true),
+        };
+        for iter in 0..self.max_iterations {
+            let f = system.derivative(u, &y);
+            let residual: [f64; N] = std::array::from_fn(|i| y[i] - rhs[i] - beta_h * f[i]);
+            let mut mat = jac;
+            for i in 0..N {
+                for j in 0..N {
+                    mat[i][j] *= -beta_h;
+                }
+                mat[i][i] += 1.;
+            }
+            let delta = match solve_linear(mat, residual) {
+                Some(delta) => delta,
+                None => break,
+            };
+            let mut err = 0f64;
+            for i in 0..N {
+                y[i] -= delta[i];
+                err = err.max(delta[i].abs());
+            }
+            if err < self.tolerance {
+                break;
+            }
+            if !fresh && iter >= 1 {
+                jac = system.jacobian(u, &y);
+                fresh = true;
+            }
+        }
+        self.jac = Some(jac);
+        y
+    }
+}
+
+impl<const N: usize> Integrator<N> for Bdf2<N> {
+    fn step<S: OdeSystem<N>>(&mut self, system: &S, u: f64, x: &mut [f64; N], h: f64) {
+        let next = match self.prev {
+            Some((prev, prev_h)) if prev_h == h => {
+                let rhs = std::array::from_fn(|i| (4. * x[i] - prev[i]) / 3.);
+                self.solve(system, u, &rhs, 2. / 3. * h, *x)
+            }
+            _ => self.solve(system, u, x, h, *x),
+        };
+        self.prev = Some((*x, h));
+        *x = next;
+    }
+
+    fn reset(&mut self) {
+        self.prev = None;
+        self.jac = None;
+    }
+}
+
+/// Exponential integrator: `x += ∫₀ʰ exp(J s) ds f(x)`, where `J` is the Jacobian of the system.
+///
+/// This is the exact discretization of linear circuits (and their zero-order hold equivalent),
+/// unconditionally stable and without any frequency warping. For an [`RcFilter`] it reduces to
+/// `v_c += (1 - exp(-h / rc)) (v_in - v_c)`. Nonlinear systems are linearized at the start of each
+/// step.
+///
+/// The matrix exponential is only recomputed when the Jacobian or the step size changes.
+///
+/// [`RcFilter`]: crate::lpf::RcFilter
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Exponential<const N: usize> {
+    cache: Option<(Matrix<N>, f64, Matrix<N>)>,
+}
+
+impl<const N: usize> Integrator<N> for Exponential<N> {
+    fn step<S: OdeSystem<N>>(&mut self, system: &S, u: f64, x: &mut [f64; N], h: f64) {
+        let jac = system.jacobian(u, x);
+        let int = match self.cache {
+            Some((cached_jac, cached_h, int)) if cached_jac == jac && cached_h == h => int,
+            _ => {
+                let (_, int) = expm_integral(&jac, h);
+                self.cache = Some((jac, h, int));
+                int
+            }
+        };
+        let dx = mat_vec(&int, &system.derivative(u, x));
+        for i in 0..N {
+            x[i] += dx[i];
+        }
+    }
+
+    fn reset(&mut self) {
+        self.cache = None;
+    }
+}
+
+/// Runtime selection of the integration method used by a filter state.
+#[derive(Debug, Default, Clone, Copy)]
+pub enum Solver<const N: usize> {
+    #[default]
+    Euler,
+    Heun,
+    Rk4,
+    Trapezoidal(Trapezoidal),
+    DormandPrince(DormandPrince),
+    Bdf2(Bdf2<N>),
+    Exponential(Exponential<N>),
+}
+
+impl<const N: usize> Solver<N> {
+    /// Number of substeps taken during the last step. Fixed-step methods always take one.
+    pub fn substeps(&self) -> usize {
+        match self {
+            Self::DormandPrince(dp) => dp.substeps(),
+            _ => 1,
+        }
+    }
+}
+
+impl<const N: usize> Integrator<N> for Solver<N> {
+    fn step<S: OdeSystem<N>>(&mut self, system: &S, u: f64, x: &mut [f64; N], h: f64) {
+        match self {
+            Self::Euler => Euler.step(system, u, x, h),
+            Self::Heun => Heun.step(system, u, x, h),
+            Self::Rk4 => Rk4.step(system, u, x, h),
+            Self::Trapezoidal(trap) => trap.step(system, u, x, h),
+            Self::DormandPrince(dp) => dp.step(system, u, x, h),
+            Self::Bdf2(bdf) => bdf.step(system, u, x, h),
+            Self::Exponential(exp) => exp.step(system, u, x, h),
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Self::Euler | Self::Heun | Self::Rk4 => {}
+            Self::Trapezoidal(trap) => Integrator::<N>::reset(trap),
+            Self::DormandPrince(dp) => Integrator::<N>::reset(dp),
+            Self::Bdf2(bdf) => bdf.reset(),
+            Self::Exponential(exp) => exp.reset(),
+        }
+    }
+}
+
+/// Integration of a circuit over each sample, split into a configurable number of solver
+/// substeps.
+///
+/// The circuit parameters are interpolated across the substeps, from the ones used for the
+/// previous sample to the current ones. This buys accuracy for stiff settings without the latency
+/// of a higher oversampling factor.
+#[derive(Debug, Clone, Copy)]
+pub struct Simulation<F, const N: usize> {
+    solver: Solver<N>,
+    per_sample: usize,
+    last: Option<F>,
+    taken: usize,
+}
+
+impl<F, const N: usize> Default for Simulation<F, N> {
+    fn default() -> Self {
+        Self::new(Solver::default())
+    }
+}
+
+impl<F, const N: usize> Simulation<F, N> {
+    pub fn new(solver: Solver<N>) -> Self {
+        Self {
+            solver,
+            per_sample: 1,
+            last: None,
+            taken: 0,
+        }
+    }
+
+    pub fn set_solver(&mut self, solver: Solver<N>) {
+        self.solver = solver;
+    }
+
+    /// Set the number of solver steps per sample.
+    pub fn set_substeps(&mut self, substeps: usize) {
+        self.per_sample = substeps.max(1);
+    }
+
+    /// Number of solver substeps taken during the last sample, including the ones taken
+    /// internally by adaptive solvers.
+    pub fn substeps(&self) -> usize {
+        self.taken
+    }
+
+    pub fn reset(&mut self) {
+        self.solver.reset();
+        self.last = None;
+    }
+}
+
+impl<F: OdeSystem<N> + Lerp + Copy, const N: usize> Simulation<F, N> {
+    /// Advance the state `x` of the circuit by one sample of length `step`.
+    pub fn process(&mut self, system: &F, u: f64, x: &mut [f64; N], step: f64) {
+        let h = step / self.per_sample as f64;
+        let from = self.last.unwrap_or(*system);
+        self.taken = 0;
+        for i in 1..=self.per_sample {
+            let t = i as f64 / self.per_sample as f64;
+            self.solver.step(&from.lerp(system, t), u, x, h);
+            self.taken += self.solver.substeps();
+        }
+        self.last = Some(*system);
+    }
+}
+
+fn add_scaled<const N: usize>(x: &[f64; N], h: f64, dx: &[f64; N]) -> [f64; N] {
+    std::array::from_fn(|i| x[i] + h * dx[i])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use approx::assert_abs_diff_eq;
+    use num_traits::Float;
+
+    use crate::math::Matrix;
+
+    use super::{Bdf2, DormandPrince, Exponential, Integrator, OdeSystem, Solver, Trapezoidal};
+
+    /// `x' = -x`, whose solution from `x(0) = 1` is `exp(-t)`.
+    struct Decay;
+
+    impl OdeSystem<1> for Decay {
+        fn derivative<T: Float>(&self, _u: T, x: &[T; 1]) -> [T; 1] {
+            [-x[0]]
+        }
+
+        fn output(&self, _u: f64, x: &[f64; 1]) -> f64 {
+            x[0]
+        }
+    }
+
+    /// Slow stage driving a stage a hundred thousand times faster, counting Jacobian evaluations.
+    #[derive(Default)]
+    struct TwoStage {
+        jacobians: Cell<usize>,
+    }
+
+    impl OdeSystem<2> for TwoStage {
+        fn derivative<T: Float>(&self, _u: T, x: &[T; 2]) -> [T; 2] {
+            [-x[0], -T::from(1e5).unwrap() * (x[1] - x[0])]
+        }
+
+        fn output(&self, _u: f64, x: &[f64; 2]) -> f64 {
+            x[1]
+        }
+
+        fn jacobian(&self, _u: f64, _x: &[f64; 2]) -> Matrix<2> {
+            self.jacobians.set(self.jacobians.get() + 1);
+            [[-1., 0.], [1e5, -1e5]]
+        }
+    }
+
+    fn integrate(mut solver: Solver<1>, h: f64) -> f64 {
+        let mut x = [1.];
+        let steps = (1. / h).round() as usize;
+        for _ in 0..steps {
+            solver.step(&Decay, 0., &mut x, h);
+        }
+        x[0]
+    }
+
+    /// `x' = -x³`, with solution `1 / sqrt(1 + 2t)` from `x(0) = 1`.
+    struct Cubic;
+
+    impl OdeSystem<1> for Cubic {
+        fn derivative<T: Float>(&self, _u: T, x: &[T; 1]) -> [T; 1] {
+            [-x[0].powi(3)]
+        }
+
+        fn output(&self, _u: f64, x: &[f64; 1]) -> f64 {
+            x[0]
+        }
+    }
+
+    #[test]
+    fn test_solvers_converge() {
+        let expected = f64::exp(-1.);
+        let solvers = [
+            (Solver::Euler, 1e-2),
+            (Solver::Heun, 1e-4),
+            (Solver::Rk4, 1e-9),
+            (Solver::Trapezoidal(Trapezoidal::default()), 1e-4),
+            (Solver::DormandPrince(DormandPrince::default()), 1e-7),
+            (Solver::Bdf2(Bdf2::default()), 1e-4),
+            (Solver::Exponential(Exponential::default()), 1e-12),
+        ];
+        for (solver, epsilon) in solvers {
+            assert_abs_diff_eq!(integrate(solver, 1e-2), expected, epsilon = epsilon);
+        }
+    }
+
+    #[test]
+    fn test_trapezoidal_stiff_stable() {
+        // Forward Euler diverges for steps longer than twice the time constant
+        let mut x = [1.];
+        let mut solver = Trapezoidal::default();
+        for _ in 0..100 {
+            solver.step(&Decay, 0., &mut x, 10.);
+        }
+        assert!(x[0].abs() < 1.);
+    }
+
+    #[test]
+    fn test_trapezoidal_nonlinear() {
+        let mut x = [1.];
+        let mut solver = Trapezoidal::default();
+        for _ in 0..100 {
+            solver.step(&Cubic, 0., &mut x, 4e-2);
+        }
+        assert_abs_diff_eq!(x[0], 1. / f64::sqrt(1. + 2. * 4.), epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_dormand_prince_substeps() {
+        let mut solver = DormandPrince::default();
+        let mut x = [1.];
+        solver.step(&Cubic, 0., &mut x, 1.);
+        let transient = solver.substeps();
+        assert!(transient > 1);
+        assert_abs_diff_eq!(x[0], 1. / f64::sqrt(3.), epsilon = 1e-6);
+
+        // Once the transient has died down, the controller settles on fewer substeps
+        let mut x = [1e-3];
+        solver.step(&Cubic, 0., &mut x, 1.);
+        assert!(solver.substeps() < transient);
+
+        // The substep budget caps the work, finishing the step in one go when it runs out
+        let mut solver = DormandPrince {
+            max_substeps: 3,
+            ..DormandPrince::default()
+        };
+        let mut x = [1.];
+        solver.step(&Cubic, 0., &mut x, 1.);
+        assert_eq!(solver.substeps(), 3);
+        assert!(x[0].is_finite());
+    }
+
+    #[test]
+    fn test_bdf2_stiff() {
+        let system = TwoStage::default();
+        let mut solver = Bdf2::default();
+        let mut x = [1., 0.];
+        for _ in 0..100 {
+            solver.step(&system, 0., &mut x, 1e-2);
+        }
+        assert_abs_diff_eq!(x[0], f64::exp(-1.), epsilon = 1e-4);
+        assert_abs_diff_eq!(x[1], x[0], epsilon = 1e-4);
+        // Linear system: the Jacobian never needs refreshing
+        assert_eq!(system.jacobians.get(), 1);
+    }
+}
@@ -0,0 +1,259 @@
+#![allow(dead_code)]
+//! State-space representation of linear circuits.
+//!
+//! Linear circuit models export their continuous-time `(A, B, C, D)` matrices through
+//! [`LinearCircuit`], which gives a single path to simulate them at a given sample rate, and to
+//! compare the analog and discretized frequency responses.
+
+use std::f64::consts::TAU;
+
+use rustfft::num_complex::Complex;
+
+use crate::math::{expm_integral, identity, inverse, mat_mul, mat_vec, solve_linear, Matrix};
+
+/// Single-input single-output linear system `x' = A x + B u`, `y = C x + D u`.
+#[derive(Debug, Clone, Copy)]
+pub struct StateSpace<const N: usize> {
+    pub a: Matrix<N>,
+    pub b: [f64; N],
+    pub c: [f64; N],
+    pub d: f64,
+}
+
+/// Linear circuit model which can be expressed as a [`StateSpace`] system.
+pub trait LinearCircuit<const N: usize> {
+    fn state_space(&self) -> StateSpace<N>;
+}
+
+/// Method used to turn a continuous-time system into a discrete-time one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Discretization {
+    /// Bilinear transform (trapezoidal rule), with the frequency response matching the analog one
+    /// exactly at `prewarp` Hz. No prewarping is done when `prewarp` is zero.
+    Bilinear { prewarp: f64 },
+    /// Zero-order hold: exact response to an input held constant over each sample.
+    Zoh,
+    /// Matched pole mapping `z = exp(s h)` without the hold delay of [`Self::Zoh`], with the gain
+    /// corrected to match at DC.
+    Matched,
+}
+
+impl<const N: usize> StateSpace<N> {
+    /// Analog frequency response at `freq` Hz.
+    pub fn frequency_response(&self, freq: f64) -> Complex<f64> {
+        transfer(
+            &self.a,
+            &self.b,
+            &self.c,
+            self.d,
+            Complex::new(0., TAU * freq),
+        )
+    }
+
+    pub fn dc_gain(&self) -> f64 {
+        self.frequency_response(0.).re
+    }
+
+    /// Discrete-time system for a sample period of `step`. The bilinear transform doesn't exist
+    /// when the system has a pole right where it maps `z = ∞`, at `s = 1 / α`, in which case this
+    /// returns `None`; the other methods always succeed, so that callers can fall back on them.
+    pub fn discretize(&self, method: Discretization, step: f64) -> Option<DiscreteStateSpace<N>> {
+        let sys = match method {
+            Discretization::Bilinear { prewarp } => {
+                // s = (z - 1) / (alpha (z + 1)), with alpha = h / 2 when not prewarping
+                let alpha = if prewarp > 0. {
+                    let w = TAU * prewarp;
+                    (w * step / 2.).tan() / w
+                } else {
+                    step / 2.
+                };
+                let mut lhs = identity::<N>();
+                let mut rhs = identity::<N>();
+                for i in 0..N {
+                    for j in 0..N {
+                        lhs[i][j] -= alpha * self.a[i][j];
+                        rhs[i][j] += alpha * self.a[i][j];
+                    }
+                }
+                let m = inverse(&lhs)?;
+                let mb = mat_vec(&m, &self.b);
+                let sqrt = (2. * alpha).sqrt();
+                DiscreteStateSpace {
+                    a: mat_mul(&rhs, &m),
+                    b: mb.map(|x| sqrt * x),
+                    c: std::array::from_fn(|j| {
+                        sqrt * (0..N).map(|i| self.c[i] * m[i][j]).sum::<f64>()
+                    }),
+                    d: self.d + alpha * dot(&self.c, &mb),
+                    step,
+                }
+            }
+            Discretization::Zoh => {
+                let (a, int) = expm_integral(&self.a, step);
+                DiscreteStateSpace {
+                    a,
+                    b: mat_vec(&int, &self.b),
+                    c: self.c,
+                    d: self.d,
+                    step,
+                }
+            }
+            Discretization::Matched => {
+                let (a, _) = expm_integral(&self.a, step);
+                let mut sys = DiscreteStateSpace {
+                    b: mat_vec(&a, &self.b),
+                    a,
+                    c: self.c,
+                    d: self.d,
+                    step,
+                };
+                let gain = (self.dc_gain() - self.d) / (sys.dc_gain() - self.d);
+                if gain.is_finite() {
+                    sys.b = sys.b.map(|x| gain * x);
+                }
+                sys
+            }
+        };
+        Some(sys)
+    }
+}
+
+/// Discrete-time system `x[n + 1] = A x[n] + B u[n]`, `y[n] = C x[n] + D u[n]`.
+#[derive(Debug, Clone, Copy)]
+pub struct DiscreteStateSpace<const N: usize> {
+    pub a: Matrix<N>,
+    pub b: [f64; N],
+    pub c: [f64; N],
+    pub d: f64,
+    pub step: f64,
+}
+
+impl<const N: usize> DiscreteStateSpace<N> {
+    /// Process one sample of input, updating the state `x`.
+    pub fn process(&self, x: &mut [f64; N], u: f64) -> f64 {
+        let y = dot(&self.c, x) + self.d * u;
+        let ax = mat_vec(&self.a, x);
+        *x = std::array::from_fn(|i| ax[i] + self.b[i] * u);
+        y
+    }
+
+    /// Frequency response at `freq` Hz, evaluated on the unit circle.
+    pub fn frequency_response(&self, freq: f64) -> Complex<f64> {
+        let z = Complex::from_polar(1., TAU * freq * self.step);
+        transfer(&self.a, &self.b, &self.c, self.d, z)
+    }
+
+    pub fn dc_gain(&self) -> f64 {
+        self.frequency_response(0.).re
+    }
+}
+
+/// Evaluate `C (s I - A)⁻¹ B + D`.
+fn transfer<const N: usize>(
+    a: &Matrix<N>,
+    b: &[f64; N],
+    c: &[f64; N],
+    d: f64,
+    s: Complex<f64>,
+) -> Complex<f64> {
+    let m = std::array::from_fn(|i| {
+        std::array::from_fn(|j| {
+            if i == j {
+                s - a[i][j]
+            } else {
+                -Complex::from(a[i][j])
+            }
+        })
+    });
+    let x = solve_linear(m, b.map(Complex::from)).unwrap_or([Complex::new(f64::INFINITY, 0.); N]);
+    (0..N).map(|i| c[i] * x[i]).sum::<Complex<f64>>() + d
+}
+
+fn dot<const N: usize>(a: &[f64; N], b: &[f64; N]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use approx::assert_abs_diff_eq;
+
+    use crate::lpf::{RcFilter, SallenKey};
+
+    use super::{Discretization, LinearCircuit, StateSpace};
+
+    #[test]
+    fn test_rc_zoh_step_response() {
+        let fc = 1e3;
+        let step = 1e-4;
+        let sys = RcFilter::new(fc)
+            .state_space()
+            .discretize(Discretization::Zoh, step)
+            .unwrap();
+        let mut x = [0.];
+        for n in 1..=20 {
+            sys.process(&mut x, 1.);
+            let t = n as f64 * step;
+            assert_abs_diff_eq!(x[0], 1. - f64::exp(-TAU * fc * t), epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_sallen_key_analog_response() {
+        let (fc, q) = (1e3, 2.);
+        let sys = SallenKey::new(fc, q).state_space();
+        let k = 3. - q.recip();
+        assert_abs_diff_eq!(sys.dc_gain(), k, epsilon = 1e-12);
+        // At the natural frequency, |H| = k Q
+        assert_abs_diff_eq!(sys.frequency_response(fc).norm(), k * q, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_discretizations() {
+        let (fc, q) = (5e3, 0.707);
+        let step = (48e3f64).recip();
+        let sys = SallenKey::new(fc, q).state_space();
+        for method in [
+            Discretization::Bilinear { prewarp: 0. },
+            Discretization::Bilinear { prewarp: fc },
+            Discretization::Zoh,
+            Discretization::Matched,
+        ] {
+            let disc = sys.discretize(method, step).unwrap();
+            assert_abs_diff_eq!(disc.dc_gain(), sys.dc_gain(), epsilon = 1e-9);
+        }
+
+        // Prewarping makes the response match exactly at the prewarp frequency, which the plain
+        // bilinear transform doesn't
+        let warped = sys
+            .discretize(Discretization::Bilinear { prewarp: 0. }, step)
+            .unwrap();
+        let prewarped = sys
+            .discretize(Discretization::Bilinear { prewarp: fc }, step)
+            .unwrap();
+        let analog = sys.frequency_response(fc);
+        assert_abs_diff_eq!(
+            prewarped.frequency_response(fc).norm(),
+            analog.norm(),
+            epsilon = 1e-9
+        );
+        assert!((warped.frequency_response(fc).norm() - analog.norm()).abs() > 1e-3);
+    }
+
+    #[test]
+    fn test_bilinear_singular() {
+        // Unstable pole at `s = 2 / h`, which the bilinear transform sends to infinity
+        let step = 1e-4;
+        let sys = StateSpace {
+            a: [[2. / step]],
+            b: [1.],
+            c: [1.],
+            d: 0.,
+        };
+        assert!(sys
+            .discretize(Discretization::Bilinear { prewarp: 0. }, step)
+            .is_none());
+        assert!(sys.discretize(Discretization::Zoh, step).is_some());
+    }
+}
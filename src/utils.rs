@@ -26,6 +26,17 @@ pub fn lerp_buf<T: Float>(output: &mut [T], input: &[T]) {
     }
 }
 
+/// Linear interpolation between two sets of parameters, `t` going from 0 (`self`) to 1 (`other`).
+pub trait Lerp {
+    fn lerp(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Lerp for f64 {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        self + t * (other - self)
+    }
+}
+
 pub fn normalize<T: Copy + Sum<T> + NumAssign>(normalize: &mut [T]) {
     let sum = normalize.iter().copied().sum::<T>();
     for s in normalize {